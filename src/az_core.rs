@@ -1,4 +1,14 @@
+use crate::az_return_codes::AzReturnCode;
 use azsys;
+use core::convert::TryFrom;
+use core::marker::PhantomData;
+use core::slice;
+use core::str;
+use core::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(feature = "std")]
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(feature = "std")]
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 pub fn precondition_failed_set_callback(callback: azsys::az_precondition_failed_fn) {
     unsafe { azsys::az_precondition_failed_set_callback(callback) };
@@ -8,60 +18,866 @@ pub fn precondition_failed_get_callback() -> Option<unsafe extern "C" fn()> {
     unsafe { azsys::az_precondition_failed_get_callback() }
 }
 
-pub fn get_span_from_str(s: &str) -> azsys::az_span {
-    let result: azsys::az_span = azsys::az_span {
-        _internal: azsys::az_span__bindgen_ty_1 {
-            ptr: s.as_ptr() as *mut u8,
-            size: s.len() as i32,
-        },
-    };
+#[cfg(feature = "std")]
+type PreconditionHandler = Box<dyn Fn() + Send + Sync>;
+
+#[cfg(feature = "std")]
+static PRECONDITION_HANDLER: RwLock<Option<PreconditionHandler>> = RwLock::new(Option::None);
 
-    result
+/// Runs handler when the C SDK detects a precondition failure, for example to log or to reset
+/// a watchdog. The SDK cannot continue safely after a failed precondition so the process is
+/// aborted if handler returns. A panic in handler cannot unwind into the C SDK either, its
+/// message is reported by the panic hook as usual and then the process aborts.
+#[cfg(feature = "std")]
+pub fn precondition_failed_set_handler<F>(handler: F)
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    *write_lock(&PRECONDITION_HANDLER) = Option::Some(Box::new(handler));
+    precondition_failed_set_callback(Option::Some(precondition_handler_trampoline));
 }
 
-pub fn get_span_from_vector(v: &Vec<u8>) -> azsys::az_span {
-    let result: azsys::az_span = azsys::az_span {
-        _internal: azsys::az_span__bindgen_ty_1 {
-            ptr: v.as_ptr() as *mut u8,
-            size: v.capacity() as i32,
-        },
-    };
+/// Turns precondition failures into a Rust panic so that tests can catch them with
+/// should_panic. Requires the precondition-panic feature.
+///
+/// # Safety
+///
+/// The panic unwinds through the C SDK frames that detected the failure, which is undefined
+/// behaviour unless the C SDK was compiled with -fexceptions. Only enable precondition-panic
+/// for test builds where the SDK is built that way.
+#[cfg(feature = "precondition-panic")]
+pub unsafe fn precondition_failed_set_panic() {
+    let trampoline: unsafe extern "C-unwind" fn() = precondition_panic_trampoline;
+    precondition_failed_set_callback(Option::Some(core::mem::transmute::<
+        unsafe extern "C-unwind" fn(),
+        unsafe extern "C" fn(),
+    >(trampoline)));
+}
+
+static PRECONDITION_HANDLER_FN: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// As precondition_failed_set_handler for builds without std, where handler is typically used
+/// to reset the device instead of leaving it in the C SDK's infinite loop. Once handler returns
+/// the process is aborted with std, or the panic handler is called without it. handler must not
+/// panic itself.
+pub fn precondition_failed_set_handler_fn(handler: fn()) {
+    PRECONDITION_HANDLER_FN.store(handler as *mut (), Ordering::Release);
+    precondition_failed_set_callback(Option::Some(precondition_handler_fn_trampoline));
+}
+
+// A panic from the handler is left to abort the process when it reaches this extern "C" frame
+#[cfg(feature = "std")]
+unsafe extern "C" fn precondition_handler_trampoline() {
+    if let Option::Some(handler) = read_lock(&PRECONDITION_HANDLER).as_ref() {
+        handler();
+    }
+    precondition_halt();
+}
+
+unsafe extern "C" fn precondition_handler_fn_trampoline() {
+    let handler = PRECONDITION_HANDLER_FN.load(Ordering::Acquire);
+    if !handler.is_null() {
+        let handler = core::mem::transmute::<*mut (), fn()>(handler);
+        handler();
+    }
+    precondition_halt();
+}
+
+#[cfg(feature = "std")]
+fn precondition_halt() -> ! {
+    std::process::abort();
+}
+
+#[cfg(not(feature = "std"))]
+fn precondition_halt() -> ! {
+    panic!("Azure SDK precondition failed");
+}
+
+#[cfg(feature = "precondition-panic")]
+extern "C-unwind" fn precondition_panic_trampoline() {
+    panic!("Azure SDK precondition failed");
+}
+
+// All construction of az_span values from Rust memory happens in this module. A span
+// produced by the C SDK is only turned back into an AzSpan after checking that it lies
+// within a span we handed it, so it can never outlive or overrun the borrowed memory.
+
+/// A read only view of borrowed memory in the form the C SDK expects.
+#[derive(Copy, Clone)]
+pub struct AzSpan<'a> {
+    inner: azsys::az_span,
+    phantom: PhantomData<&'a [u8]>,
+}
+
+impl<'a> AzSpan<'a> {
+    pub fn empty() -> AzSpan<'a> {
+        AzSpan {
+            inner: azsys::az_span {
+                _internal: azsys::az_span__bindgen_ty_1 {
+                    ptr: core::ptr::null_mut(),
+                    size: 0,
+                },
+            },
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner._internal.size as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn as_bytes(&self) -> &'a [u8] {
+        if self.is_empty() {
+            return &[];
+        }
+
+        unsafe { slice::from_raw_parts(self.inner._internal.ptr, self.len()) }
+    }
+
+    pub fn as_str(&self) -> Result<&'a str, str::Utf8Error> {
+        str::from_utf8(self.as_bytes())
+    }
+
+    pub(crate) fn as_raw(&self) -> azsys::az_span {
+        self.inner
+    }
+
+    /// Converts a span written by the C SDK back into an AzSpan borrowing from this one.
+    /// Fails with AzResultCoreErrorArg unless the span is empty or lies within this span.
+    pub(crate) fn sub_span(&self, raw: azsys::az_span) -> Result<AzSpan<'a>, AzReturnCode> {
+        if raw._internal.size == 0 {
+            return Ok(AzSpan::empty());
+        }
+
+        let start = self.inner._internal.ptr as usize;
+        let end = start + self.len();
+        let raw_start = raw._internal.ptr as usize;
+
+        if raw._internal.size < 0 || raw_start < start || raw_start + raw._internal.size as usize > end {
+            return Err(AzReturnCode::AzResultCoreErrorArg);
+        }
+
+        Ok(AzSpan {
+            inner: raw,
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn atou32(&self) -> Result<u32, AzReturnCode> {
+        let mut result: u32 = 0;
+        self.parse_number(|span| unsafe { azsys::az_span_atou32(span, &mut result) })?;
+        Ok(result)
+    }
+
+    pub fn atoi32(&self) -> Result<i32, AzReturnCode> {
+        let mut result: i32 = 0;
+        self.parse_number(|span| unsafe { azsys::az_span_atoi32(span, &mut result) })?;
+        Ok(result)
+    }
+
+    pub fn atou64(&self) -> Result<u64, AzReturnCode> {
+        let mut result: u64 = 0;
+        self.parse_number(|span| unsafe { azsys::az_span_atou64(span, &mut result) })?;
+        Ok(result)
+    }
+
+    pub fn atoi64(&self) -> Result<i64, AzReturnCode> {
+        let mut result: i64 = 0;
+        self.parse_number(|span| unsafe { azsys::az_span_atoi64(span, &mut result) })?;
+        Ok(result)
+    }
+
+    pub fn atod(&self) -> Result<f64, AzReturnCode> {
+        let mut result: f64 = 0.0;
+        self.parse_number(|span| unsafe { azsys::az_span_atod(span, &mut result) })?;
+        Ok(result)
+    }
+
+    // The C parsers treat an empty source as a precondition failure rather than an error
+    fn parse_number<F>(&self, parse: F) -> Result<(), AzReturnCode>
+    where
+        F: FnOnce(azsys::az_span) -> azsys::az_result,
+    {
+        if self.is_empty() {
+            return Err(AzReturnCode::AzResultCoreErrorUnexpectedChar);
+        }
+
+        AzReturnCode::from_i32(parse(self.inner)).into_result()
+    }
+
+    /// Returns the offset of the first occurrence of target, an empty target is found at 0.
+    pub fn find(&self, target: AzSpan) -> Option<usize> {
+        let index = unsafe { azsys::az_span_find(self.inner, target.inner) };
+
+        if index < 0 {
+            Option::None
+        } else {
+            Option::Some(index as usize)
+        }
+    }
 
-    result
+    pub fn is_content_equal(&self, other: AzSpan) -> bool {
+        unsafe { azsys::az_span_is_content_equal(self.inner, other.inner) }
+    }
+
+    pub fn slice(&self, start: usize, end: usize) -> Result<AzSpan<'a>, AzReturnCode> {
+        if start > end || end > self.len() {
+            return Err(AzReturnCode::AzResultCoreErrorArg);
+        }
+
+        if start == end {
+            return Ok(AzSpan::empty());
+        }
+
+        Ok(AzSpan {
+            inner: unsafe { azsys::az_span_slice(self.inner, start as i32, end as i32) },
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn slice_to_end(&self, start: usize) -> Result<AzSpan<'a>, AzReturnCode> {
+        self.slice(start, self.len())
+    }
+}
+
+impl<'a> From<&'a str> for AzSpan<'a> {
+    fn from(s: &'a str) -> AzSpan<'a> {
+        AzSpan::from(s.as_bytes())
+    }
+}
+
+/// Panics if s is longer than i32::MAX bytes, the most an az_span can describe.
+impl<'a> From<&'a [u8]> for AzSpan<'a> {
+    fn from(s: &'a [u8]) -> AzSpan<'a> {
+        AzSpan {
+            inner: azsys::az_span {
+                _internal: azsys::az_span__bindgen_ty_1 {
+                    ptr: s.as_ptr() as *mut u8,
+                    size: span_size(s.len()),
+                },
+            },
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a> From<AzSpan<'a>> for &'a [u8] {
+    fn from(span: AzSpan<'a>) -> &'a [u8] {
+        span.as_bytes()
+    }
+}
+
+impl<'a> TryFrom<AzSpan<'a>> for &'a str {
+    type Error = str::Utf8Error;
+
+    fn try_from(span: AzSpan<'a>) -> Result<&'a str, str::Utf8Error> {
+        span.as_str()
+    }
+}
+
+/// A writable view of borrowed memory for the C SDK to fill.
+pub struct AzSpanMut<'a> {
+    inner: azsys::az_span,
+    phantom: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> AzSpanMut<'a> {
+    pub fn len(&self) -> usize {
+        self.inner._internal.size as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.as_span().as_bytes()
+    }
+
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        if self.is_empty() {
+            return &mut [];
+        }
+
+        unsafe { slice::from_raw_parts_mut(self.inner._internal.ptr, self.len()) }
+    }
+
+    pub fn as_span(&self) -> AzSpan<'_> {
+        AzSpan {
+            inner: self.inner,
+            phantom: PhantomData,
+        }
+    }
+
+    pub(crate) fn as_raw(&self) -> azsys::az_span {
+        self.inner
+    }
+
+    /// Copies source to the start of this span and returns the number of bytes copied.
+    pub fn copy_from(&mut self, source: AzSpan) -> Result<usize, AzReturnCode> {
+        if source.len() > self.len() {
+            return Err(AzReturnCode::AzResultCoreErrorNotEnoughSpace);
+        }
+
+        if !source.is_empty() {
+            unsafe { azsys::az_span_copy(self.inner, source.inner) };
+        }
+
+        Ok(source.len())
+    }
+
+    pub fn u32toa(&mut self, value: u32) -> Result<&str, AzReturnCode> {
+        self.write_number(|span, out| unsafe { azsys::az_span_u32toa(span, value, out) })
+    }
+
+    pub fn i32toa(&mut self, value: i32) -> Result<&str, AzReturnCode> {
+        self.write_number(|span, out| unsafe { azsys::az_span_i32toa(span, value, out) })
+    }
+
+    pub fn u64toa(&mut self, value: u64) -> Result<&str, AzReturnCode> {
+        self.write_number(|span, out| unsafe { azsys::az_span_u64toa(span, value, out) })
+    }
+
+    pub fn i64toa(&mut self, value: i64) -> Result<&str, AzReturnCode> {
+        self.write_number(|span, out| unsafe { azsys::az_span_i64toa(span, value, out) })
+    }
+
+    /// Writes value with at most fractional_digits (0 to 15) digits after the decimal point.
+    pub fn dtoa(&mut self, value: f64, fractional_digits: u8) -> Result<&str, AzReturnCode> {
+        if fractional_digits > 15 || !value.is_finite() {
+            return Err(AzReturnCode::AzResultCoreErrorArg);
+        }
+
+        self.write_number(|span, out| unsafe { azsys::az_span_dtoa(span, value, fractional_digits as i32, out) })
+    }
+
+    // Runs one of the C number writers and returns the text it wrote at the start of the span
+    fn write_number<F>(&mut self, write: F) -> Result<&str, AzReturnCode>
+    where
+        F: FnOnce(azsys::az_span, *mut azsys::az_span) -> azsys::az_result,
+    {
+        if self.is_empty() {
+            return Err(AzReturnCode::AzResultCoreErrorNotEnoughSpace);
+        }
+
+        let mut remainder = AzSpan::empty().as_raw();
+        AzReturnCode::from_i32(write(self.inner, &mut remainder)).into_result()?;
+
+        let written = self.len() - self.as_span().sub_span(remainder)?.len();
+        str::from_utf8(&self.as_bytes()[..written]).map_err(|_| AzReturnCode::AzResultCoreErrorUnexpectedChar)
+    }
 }
 
-pub fn get_empty_span() -> azsys::az_span {
-    let result: azsys::az_span = azsys::az_span {
-        _internal: azsys::az_span__bindgen_ty_1 {
-            ptr: std::ptr::null_mut(),
-            size: 0,
-        },
+/// Panics if s is longer than i32::MAX bytes, the most an az_span can describe.
+impl<'a> From<&'a mut [u8]> for AzSpanMut<'a> {
+    fn from(s: &'a mut [u8]) -> AzSpanMut<'a> {
+        AzSpanMut {
+            inner: azsys::az_span {
+                _internal: azsys::az_span__bindgen_ty_1 {
+                    ptr: s.as_mut_ptr(),
+                    size: span_size(s.len()),
+                },
+            },
+            phantom: PhantomData,
+        }
+    }
+}
+
+// az_span sizes are i32, a longer slice would wrap and len would then overrun it
+fn span_size(len: usize) -> i32 {
+    assert!(len <= i32::MAX as usize, "slice too long for an az_span");
+    len as i32
+}
+
+/// Classifications the C SDK attaches to its log messages.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum LogClassification {
+    HttpRequest,
+    HttpResponse,
+    HttpRetry,
+    MqttReceivedTopic,
+    MqttReceivedPayload,
+    IotRetry,
+    IotSasToken,
+    IotAzureRtos,
+    /// A classification this version of the crate does not recognise.
+    Unknown(i32),
+}
+
+static LOG_CLASSIFICATIONS: [(LogClassification, azsys::az_log_classification); 8] = [
+    (LogClassification::HttpRequest, azsys::az_log_classification_core_AZ_LOG_HTTP_REQUEST),
+    (LogClassification::HttpResponse, azsys::az_log_classification_core_AZ_LOG_HTTP_RESPONSE),
+    (LogClassification::HttpRetry, azsys::az_log_classification_core_AZ_LOG_HTTP_RETRY),
+    (
+        LogClassification::MqttReceivedTopic,
+        azsys::az_log_classification_iot_AZ_LOG_MQTT_RECEIVED_TOPIC as azsys::az_log_classification,
+    ),
+    (
+        LogClassification::MqttReceivedPayload,
+        azsys::az_log_classification_iot_AZ_LOG_MQTT_RECEIVED_PAYLOAD as azsys::az_log_classification,
+    ),
+    (
+        LogClassification::IotRetry,
+        azsys::az_log_classification_iot_AZ_LOG_IOT_RETRY as azsys::az_log_classification,
+    ),
+    (
+        LogClassification::IotSasToken,
+        azsys::az_log_classification_iot_AZ_LOG_IOT_SAS_TOKEN as azsys::az_log_classification,
+    ),
+    (
+        LogClassification::IotAzureRtos,
+        azsys::az_log_classification_iot_AZ_LOG_IOT_AZURERTOS as azsys::az_log_classification,
+    ),
+];
+
+impl LogClassification {
+    pub fn from_i32(value: i32) -> LogClassification {
+        LOG_CLASSIFICATIONS
+            .iter()
+            .find(|(_, raw)| *raw == value)
+            .map(|(classification, _)| *classification)
+            .unwrap_or(LogClassification::Unknown(value))
+    }
+
+    pub fn as_i32(&self) -> i32 {
+        match self {
+            LogClassification::Unknown(value) => *value,
+            _ => {
+                LOG_CLASSIFICATIONS
+                    .iter()
+                    .find(|(classification, _)| classification == self)
+                    .expect("Classification missing from table")
+                    .1
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+type LogMessageCallback = Box<dyn Fn(LogClassification, &str) + Send + Sync>;
+#[cfg(feature = "std")]
+type LogFilterCallback = Box<dyn Fn(LogClassification) -> bool + Send + Sync>;
+
+#[cfg(feature = "std")]
+static LOG_MESSAGE_CALLBACK: RwLock<Option<LogMessageCallback>> = RwLock::new(Option::None);
+#[cfg(feature = "std")]
+static LOG_FILTER_CALLBACK: RwLock<Option<LogFilterCallback>> = RwLock::new(Option::None);
+
+/// Routes every log message the C SDK produces to callback. Messages that are not valid
+/// UTF-8 are passed on with replacement characters. The callback must not register or clear
+/// log callbacks itself.
+#[cfg(feature = "std")]
+pub fn log_set_message_callback<F>(callback: F)
+where
+    F: Fn(LogClassification, &str) + Send + Sync + 'static,
+{
+    *write_lock(&LOG_MESSAGE_CALLBACK) = Option::Some(Box::new(callback));
+    unsafe { azsys::az_log_set_message_callback(Option::Some(log_message_trampoline)) };
+}
+
+static LOG_MESSAGE_CALLBACK_FN: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+static LOG_FILTER_CALLBACK_FN: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// As log_set_message_callback without std. Messages that are not valid UTF-8 are cut short at
+/// the first invalid byte rather than copied. callback must not panic.
+pub fn log_set_message_callback_fn(callback: fn(LogClassification, &str)) {
+    LOG_MESSAGE_CALLBACK_FN.store(callback as *mut (), Ordering::Release);
+    unsafe { azsys::az_log_set_message_callback(Option::Some(log_message_fn_trampoline)) };
+}
+
+/// Clears the callback set by either log_set_message_callback or log_set_message_callback_fn.
+pub fn log_clear_message_callback() {
+    unsafe { azsys::az_log_set_message_callback(Option::None) };
+    LOG_MESSAGE_CALLBACK_FN.store(core::ptr::null_mut(), Ordering::Release);
+    #[cfg(feature = "std")]
+    {
+        *write_lock(&LOG_MESSAGE_CALLBACK) = Option::None;
+    }
+}
+
+/// Only classifications for which filter returns true are formatted and passed to the
+/// message callback. Without a filter every classification is logged.
+#[cfg(feature = "std")]
+pub fn log_set_classification_filter<F>(filter: F)
+where
+    F: Fn(LogClassification) -> bool + Send + Sync + 'static,
+{
+    *write_lock(&LOG_FILTER_CALLBACK) = Option::Some(Box::new(filter));
+    unsafe { azsys::az_log_set_classification_filter_callback(Option::Some(log_filter_trampoline)) };
+}
+
+/// Limits logging to the listed classifications.
+#[cfg(feature = "std")]
+pub fn log_set_classifications(classifications: &[LogClassification]) {
+    log_set_classification_filter(classifications_filter(classifications));
+}
+
+#[cfg(feature = "std")]
+fn classifications_filter(classifications: &[LogClassification]) -> impl Fn(LogClassification) -> bool {
+    let classifications = classifications.to_vec();
+    move |classification| classifications.contains(&classification)
+}
+
+/// As log_set_classification_filter without std. filter must not panic.
+pub fn log_set_classification_filter_fn(filter: fn(LogClassification) -> bool) {
+    LOG_FILTER_CALLBACK_FN.store(filter as *mut (), Ordering::Release);
+    unsafe { azsys::az_log_set_classification_filter_callback(Option::Some(log_filter_fn_trampoline)) };
+}
+
+pub fn log_clear_classification_filter() {
+    unsafe { azsys::az_log_set_classification_filter_callback(Option::None) };
+    LOG_FILTER_CALLBACK_FN.store(core::ptr::null_mut(), Ordering::Release);
+    #[cfg(feature = "std")]
+    {
+        *write_lock(&LOG_FILTER_CALLBACK) = Option::None;
+    }
+}
+
+#[cfg(feature = "log")]
+const LOG_TARGET: &str = "azure_embedded_sdk";
+
+/// Forwards C SDK log messages to the log crate under the azure_embedded_sdk target. Only
+/// classifications whose level is enabled for that target are produced by the SDK.
+#[cfg(feature = "log")]
+pub fn log_to_log_crate() {
+    log_set_classification_filter(|classification| log::log_enabled!(target: LOG_TARGET, log_level(classification)));
+    log_set_message_callback(|classification, message| {
+        log::log!(target: LOG_TARGET, log_level(classification), "{:?}: {}", classification, message)
+    });
+}
+
+#[cfg(feature = "log")]
+fn log_level(classification: LogClassification) -> log::Level {
+    match classification {
+        LogClassification::HttpRetry | LogClassification::IotRetry => log::Level::Info,
+        LogClassification::Unknown(_) => log::Level::Trace,
+        _ => log::Level::Debug,
+    }
+}
+
+#[cfg(feature = "std")]
+fn read_lock<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(feature = "std")]
+fn write_lock<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(feature = "std")]
+// Panics are caught here since they must not unwind into the C SDK
+unsafe extern "C" fn log_message_trampoline(classification: azsys::az_log_classification, message: azsys::az_span) {
+    let message = AzSpan {
+        inner: message,
+        phantom: PhantomData,
     };
+    let text = String::from_utf8_lossy(message.as_bytes());
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        if let Option::Some(callback) = read_lock(&LOG_MESSAGE_CALLBACK).as_ref() {
+            callback(LogClassification::from_i32(classification), &text);
+        }
+    }));
+}
 
-    result
+#[cfg(feature = "std")]
+unsafe extern "C" fn log_filter_trampoline(classification: azsys::az_log_classification) -> bool {
+    panic::catch_unwind(AssertUnwindSafe(|| match read_lock(&LOG_FILTER_CALLBACK).as_ref() {
+        Option::Some(filter) => filter(LogClassification::from_i32(classification)),
+        Option::None => true,
+    }))
+    .unwrap_or(false)
 }
 
-pub fn get_span_size(span: &azsys::az_span) -> i32 {
-    span._internal.size
+unsafe extern "C" fn log_message_fn_trampoline(classification: azsys::az_log_classification, message: azsys::az_span) {
+    let callback = LOG_MESSAGE_CALLBACK_FN.load(Ordering::Acquire);
+    if callback.is_null() {
+        return;
+    }
+
+    let callback = core::mem::transmute::<*mut (), fn(LogClassification, &str)>(callback);
+    let message = AzSpan {
+        inner: message,
+        phantom: PhantomData,
+    };
+    let bytes = message.as_bytes();
+    let text = match str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or(""),
+    };
+    callback(LogClassification::from_i32(classification), text);
 }
 
-pub fn get_span_ptr(span: &azsys::az_span) -> *mut u8 {
-    span._internal.ptr
+unsafe extern "C" fn log_filter_fn_trampoline(classification: azsys::az_log_classification) -> bool {
+    let filter = LOG_FILTER_CALLBACK_FN.load(Ordering::Acquire);
+    if filter.is_null() {
+        return true;
+    }
+
+    let filter = core::mem::transmute::<*mut (), fn(LogClassification) -> bool>(filter);
+    filter(LogClassification::from_i32(classification))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::{Mutex, MutexGuard};
+
+    // The precondition callback is process wide so tests that change it take turns
+    static PRECONDITION_TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    fn precondition_test_lock() -> MutexGuard<'static, ()> {
+        PRECONDITION_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Likewise for the log callbacks
+    static LOG_TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    fn log_test_lock() -> MutexGuard<'static, ()> {
+        LOG_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
     #[test]
     fn set_callback() {
+        let _lock = precondition_test_lock();
         precondition_failed_set_callback(Option::Some(callback));
     }
     #[test]
     fn get_callback() {
+        let _lock = precondition_test_lock();
         precondition_failed_set_callback(Option::Some(callback));
         let check: azsys::az_precondition_failed_fn = precondition_failed_get_callback();
         assert!(check.is_some());
     }
+    #[cfg(feature = "std")]
+    #[test]
+    fn set_handler() {
+        let _lock = precondition_test_lock();
+        precondition_failed_set_handler(|| ());
+        assert!(precondition_failed_get_callback().is_some());
+    }
+    #[test]
+    fn set_handler_fn() {
+        let _lock = precondition_test_lock();
+        precondition_failed_set_handler_fn(|| ());
+        assert!(precondition_failed_get_callback().is_some());
+        assert!(!PRECONDITION_HANDLER_FN.load(Ordering::Acquire).is_null());
+    }
+    // Needs the C SDK built with -fexceptions, see precondition_failed_set_panic
+    #[cfg(feature = "precondition-panic")]
+    #[test]
+    #[should_panic(expected = "precondition failed")]
+    fn precondition_panic() {
+        let _lock = precondition_test_lock();
+        unsafe { precondition_failed_set_panic() };
+        let mut value: u32 = 0;
+        // az_span_atou32 requires a span of at least one byte
+        unsafe { azsys::az_span_atou32(AzSpan::empty().as_raw(), &mut value) };
+    }
+    #[test]
+    fn span_from_str() {
+        let span = AzSpan::from("Hello");
+        assert_eq!(span.len(), 5);
+        assert_eq!(span.as_bytes(), b"Hello");
+        assert_eq!(span.as_str(), Ok("Hello"));
+        let s: &str = TryFrom::try_from(span).unwrap();
+        assert_eq!(s, "Hello");
+    }
+    #[test]
+    fn span_empty() {
+        let span = AzSpan::empty();
+        assert!(span.is_empty());
+        assert_eq!(span.as_bytes(), b"");
+        assert_eq!(span.as_str(), Ok(""));
+    }
+    #[test]
+    fn span_invalid_utf8() {
+        let bytes: &[u8] = &[0x66, 0xff, 0x6f];
+        assert!(AzSpan::from(bytes).as_str().is_err());
+    }
+    #[test]
+    fn span_mut_from_slice() {
+        let mut buffer = [0u8; 4];
+        let mut span = AzSpanMut::from(&mut buffer[..]);
+        assert_eq!(span.len(), 4);
+        span.as_bytes_mut().copy_from_slice(b"abcd");
+        assert_eq!(span.as_span().as_str(), Ok("abcd"));
+        assert_eq!(&buffer, b"abcd");
+    }
+    #[test]
+    fn span_sub_span() {
+        let text = "devices/test1/messages";
+        let span = AzSpan::from(text);
+        let inner = AzSpan::from(&text[8..13]);
+        assert_eq!(span.sub_span(inner.as_raw()).unwrap().as_str(), Ok("test1"));
+        assert!(span.sub_span(AzSpan::from("other").as_raw()).is_err());
+        assert!(span.sub_span(AzSpan::empty().as_raw()).unwrap().is_empty());
+    }
+    #[test]
+    fn span_atou32() {
+        assert_eq!(AzSpan::from("4294967295").atou32(), Ok(4294967295));
+        assert_eq!(AzSpan::from("4294967296").atou32(), Err(AzReturnCode::AzResultCoreErrorUnexpectedChar));
+        assert_eq!(AzSpan::from("12a").atou32(), Err(AzReturnCode::AzResultCoreErrorUnexpectedChar));
+        assert_eq!(AzSpan::empty().atou32(), Err(AzReturnCode::AzResultCoreErrorUnexpectedChar));
+    }
+    #[test]
+    fn span_atoi64() {
+        assert_eq!(AzSpan::from("-9223372036854775808").atoi64(), Ok(i64::MIN));
+        assert_eq!(AzSpan::from("18446744073709551615").atou64(), Ok(u64::MAX));
+        assert_eq!(AzSpan::from("-42").atoi32(), Ok(-42));
+    }
+    #[test]
+    fn span_atod() {
+        assert_eq!(AzSpan::from("1.5").atod(), Ok(1.5));
+        assert!(AzSpan::from("one").atod().is_err());
+    }
+    #[test]
+    fn span_number_writers() {
+        let mut buffer = [0u8; 24];
+        let mut span = AzSpanMut::from(&mut buffer[..]);
+        assert_eq!(span.u32toa(12345), Ok("12345"));
+        assert_eq!(span.i32toa(-7), Ok("-7"));
+        assert_eq!(span.u64toa(u64::MAX), Ok("18446744073709551615"));
+        assert_eq!(span.i64toa(i64::MIN), Ok("-9223372036854775808"));
+        assert_eq!(span.dtoa(1.25, 2), Ok("1.25"));
+        assert_eq!(span.dtoa(1.0, 16), Err(AzReturnCode::AzResultCoreErrorArg));
+    }
+    #[test]
+    fn span_number_writer_too_small() {
+        let mut buffer = [0u8; 2];
+        let mut span = AzSpanMut::from(&mut buffer[..]);
+        assert_eq!(span.u32toa(123), Err(AzReturnCode::AzResultCoreErrorNotEnoughSpace));
+    }
+    #[test]
+    fn span_find() {
+        let span = AzSpan::from("devices/test1/messages");
+        assert_eq!(span.find(AzSpan::from("test1")), Option::Some(8));
+        assert_eq!(span.find(AzSpan::from("test2")), Option::None);
+        assert_eq!(span.find(AzSpan::empty()), Option::Some(0));
+    }
+    #[test]
+    fn span_is_content_equal() {
+        assert!(AzSpan::from("abc").is_content_equal(AzSpan::from("abc")));
+        assert!(!AzSpan::from("abc").is_content_equal(AzSpan::from("abd")));
+        assert!(AzSpan::empty().is_content_equal(AzSpan::from("")));
+    }
+    #[test]
+    fn span_slice() {
+        let span = AzSpan::from("devices/test1/messages");
+        assert_eq!(span.slice(8, 13).unwrap().as_str(), Ok("test1"));
+        assert_eq!(span.slice_to_end(14).unwrap().as_str(), Ok("messages"));
+        assert!(span.slice(8, 8).unwrap().is_empty());
+        assert_eq!(span.slice(13, 8).err(), Option::Some(AzReturnCode::AzResultCoreErrorArg));
+        assert_eq!(span.slice(0, 23).err(), Option::Some(AzReturnCode::AzResultCoreErrorArg));
+    }
+    #[test]
+    fn span_size_limit() {
+        assert_eq!(span_size(0), 0);
+        assert_eq!(span_size(i32::MAX as usize), i32::MAX);
+    }
+    #[test]
+    #[should_panic(expected = "too long")]
+    fn span_size_overflow() {
+        span_size(i32::MAX as usize + 1);
+    }
+    #[test]
+    fn span_copy_from() {
+        let mut buffer = [0u8; 4];
+        let mut span = AzSpanMut::from(&mut buffer[..]);
+        assert_eq!(span.copy_from(AzSpan::from("ab")), Ok(2));
+        assert_eq!(span.copy_from(AzSpan::from("abcde")), Err(AzReturnCode::AzResultCoreErrorNotEnoughSpace));
+        assert_eq!(&buffer, b"ab\0\0");
+    }
+    #[test]
+    fn log_classification_round_trip() {
+        for (classification, value) in LOG_CLASSIFICATIONS.iter() {
+            assert_eq!(LogClassification::from_i32(*value), *classification);
+            assert_eq!(classification.as_i32(), *value);
+        }
+        assert_eq!(LogClassification::from_i32(12345), LogClassification::Unknown(12345));
+        assert_eq!(LogClassification::Unknown(12345).as_i32(), 12345);
+    }
+    #[cfg(feature = "std")]
+    #[test]
+    fn log_callbacks() {
+        use std::sync::Arc;
+
+        let _lock = log_test_lock();
+
+        // The callbacks are stored without registering the trampolines with the C SDK, which
+        // would otherwise pass them messages from the topic parsers in concurrently running tests
+        let received: Arc<Mutex<Vec<(LogClassification, String)>>> = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        *write_lock(&LOG_MESSAGE_CALLBACK) = Option::Some(Box::new(move |classification, message| {
+            sink.lock().unwrap().push((classification, message.to_string()));
+        }));
+        let topic = AzSpan::from("devices/test1/messages");
+        let raw = LogClassification::MqttReceivedTopic.as_i32();
+        unsafe { log_message_trampoline(raw, topic.as_raw()) };
+        unsafe { log_message_trampoline(raw, AzSpan::empty().as_raw()) };
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![
+                (LogClassification::MqttReceivedTopic, String::from("devices/test1/messages")),
+                (LogClassification::MqttReceivedTopic, String::new()),
+            ]
+        );
+
+        assert!(unsafe { log_filter_trampoline(raw) });
+        *write_lock(&LOG_FILTER_CALLBACK) = Option::Some(Box::new(classifications_filter(&[LogClassification::IotRetry])));
+        assert!(!unsafe { log_filter_trampoline(raw) });
+        assert!(unsafe { log_filter_trampoline(LogClassification::IotRetry.as_i32()) });
+        *write_lock(&LOG_FILTER_CALLBACK) = Option::Some(Box::new(|_| panic!("filter")));
+        assert!(!unsafe { log_filter_trampoline(raw) });
+        log_clear_classification_filter();
+        assert!(unsafe { log_filter_trampoline(raw) });
+
+        *write_lock(&LOG_MESSAGE_CALLBACK) = Option::Some(Box::new(|_, _| panic!("callback")));
+        unsafe { log_message_trampoline(raw, topic.as_raw()) };
+        log_clear_message_callback();
+        unsafe { log_message_trampoline(raw, topic.as_raw()) };
+        assert_eq!(received.lock().unwrap().len(), 2);
+    }
+    #[test]
+    fn log_callbacks_fn() {
+        use std::sync::atomic::{AtomicI32, AtomicUsize};
+
+        static RECEIVED: AtomicUsize = AtomicUsize::new(0);
+        static CLASSIFICATION: AtomicI32 = AtomicI32::new(0);
+
+        fn callback(classification: LogClassification, message: &str) {
+            CLASSIFICATION.store(classification.as_i32(), Ordering::SeqCst);
+            RECEIVED.fetch_add(message.len(), Ordering::SeqCst);
+        }
+
+        fn filter(classification: LogClassification) -> bool {
+            classification == LogClassification::IotRetry
+        }
+
+        // As in log_callbacks the C SDK is never handed the trampolines
+        let _lock = log_test_lock();
+        LOG_MESSAGE_CALLBACK_FN.store(callback as *mut (), Ordering::Release);
+        let raw = LogClassification::MqttReceivedTopic.as_i32();
+        unsafe { log_message_fn_trampoline(raw, AzSpan::from("devices").as_raw()) };
+        unsafe { log_message_fn_trampoline(raw, AzSpan::from(&b"ab\xFFcd"[..]).as_raw()) };
+        assert_eq!(RECEIVED.load(Ordering::SeqCst), 9);
+        assert_eq!(CLASSIFICATION.load(Ordering::SeqCst), raw);
+
+        assert!(unsafe { log_filter_fn_trampoline(raw) });
+        LOG_FILTER_CALLBACK_FN.store(filter as *mut (), Ordering::Release);
+        assert!(!unsafe { log_filter_fn_trampoline(raw) });
+        assert!(unsafe { log_filter_fn_trampoline(LogClassification::IotRetry.as_i32()) });
+        log_clear_classification_filter();
+        assert!(unsafe { log_filter_fn_trampoline(raw) });
+
+        log_clear_message_callback();
+        unsafe { log_message_fn_trampoline(raw, AzSpan::from("devices").as_raw()) };
+        assert_eq!(RECEIVED.load(Ordering::SeqCst), 9);
+    }
 
     unsafe extern "C" fn callback() {
         panic!();
@@ -1,8 +1,18 @@
 pub use crate::az_core::*;
 pub use crate::az_return_codes::AzReturnCode;
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 use azsys;
-use std::slice;
-use std::str;
+use core::cmp;
+use core::ffi::c_char;
+use core::str;
+
+#[cfg(feature = "sas-token")]
+use hmac::{Hmac, Mac};
+#[cfg(feature = "sas-token")]
+use sha2::Sha256;
 
 pub struct HubClientBuilder<'a> {
     host_name: Option<&'a str>,
@@ -12,11 +22,14 @@ pub struct HubClientBuilder<'a> {
 
 pub struct HubClient {
     inner: azsys::az_iot_hub_client,
+    host_name: String,
+    device_id: String,
+    options: Option<HubClientOptions>,
 }
 
 impl<'a> HubClientBuilder<'a> {
     
-    pub fn new() -> HubClientBuilder<'static> {
+    pub fn new() -> HubClientBuilder<'a> {
         HubClientBuilder {
             host_name: Option::None,
             device_id: Option::None,
@@ -47,40 +60,144 @@ impl<'a> HubClientBuilder<'a> {
             panic!("Missing required parameters");
         }
 
-        let options_work: *const azsys::az_iot_hub_client_options;
+        HubClient::new(
+            self.host_name.unwrap(),
+            self.device_id.unwrap(),
+            self.client_options.take(),
+        )
+    }
+}
+
+impl<'a> Default for HubClientBuilder<'a> {
+    fn default() -> Self {
+        HubClientBuilder::new()
+    }
+}
+
+pub enum TopicType<'a> {
+    C2D(ClientC2DRequest),
+    Method(ClientMethodRequest<'a>),
+    TwinGetResponse(ClientTwinResponse<'a>),
+    TwinReportedResponse(ClientTwinResponse<'a>),
+    TwinDesiredPatch(ClientTwinResponse<'a>),
+    /// A twin response the C SDK parsed but whose response type this crate does not recognise.
+    TwinResponse(ClientTwinResponse<'a>),
+    ModuleInput(ClientModuleInputRequest),
+    Unknown,
+}
 
-        match &self.client_options {
-            Some(o) => options_work = &o.inner,
-            None => options_work = std::ptr::null(),
+impl<'a> TopicType<'a> {
+    fn from_twin_response(response: ClientTwinResponse<'a>) -> TopicType<'a> {
+        match response.get_response_type() {
+            TwinResponseType::Get => TopicType::TwinGetResponse(response),
+            TwinResponseType::ReportedProperties => TopicType::TwinReportedResponse(response),
+            TwinResponseType::DesiredProperties => TopicType::TwinDesiredPatch(response),
+            TwinResponseType::Unknown => TopicType::TwinResponse(response),
         }
+    }
+}
 
-        let mut result = HubClient::new_empty();
-        let rc = unsafe {
-            azsys::az_iot_hub_client_init(
-                &mut result.inner,
-                get_span_from_str(&self.host_name.as_ref().unwrap()),
-                get_span_from_str(&self.device_id.as_ref().unwrap()),
-                options_work,
-            )
-        };
+// Allowance for the fixed text the C SDK writes around the variable parts of a topic, user
+// name or password, including the null terminator
+pub(crate) const FIXED_TEXT_ESTIMATE: usize = 128;
 
-        if rc != azsys::az_result_core_AZ_OK {
-            Err(AzReturnCode::from_i32(rc))
-        } else {
-            Ok(result)
+// Doubles so that an estimate that falls short only costs a few extra calls
+pub(crate) fn capacity_increase_policy(current: usize) -> usize {
+    cmp::max(current.saturating_mul(2), FIXED_TEXT_ESTIMATE)
+}
+
+// Upper bound on the length of text once the C SDK has percent encoded it
+pub(crate) fn url_encoded_len(text: &str) -> usize {
+    text.len() * 3
+}
+
+pub(crate) trait GrowableBuffer {
+    fn with_capacity(capacity: usize) -> Self;
+    fn capacity(&self) -> usize;
+    fn shrink_to_fit(&mut self);
+}
+
+impl GrowableBuffer for String {
+    fn with_capacity(capacity: usize) -> Self {
+        String::with_capacity(capacity)
+    }
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+    fn shrink_to_fit(&mut self) {
+        self.shrink_to_fit()
+    }
+}
+
+impl GrowableBuffer for Vec<u8> {
+    fn with_capacity(capacity: usize) -> Self {
+        Vec::with_capacity(capacity)
+    }
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+    fn shrink_to_fit(&mut self) {
+        self.shrink_to_fit()
+    }
+}
+
+// Runs one of the ll_ functions with a buffer of the estimated capacity, which is normally
+// enough for a single call, and grows the buffer for as long as it reports NotEnoughSpace
+pub(crate) fn grow_until_fits<T, F>(estimate: usize, mut write: F) -> Result<T, AzReturnCode>
+where
+    T: GrowableBuffer,
+    F: FnMut(&mut T) -> AzReturnCode,
+{
+    let mut result = T::with_capacity(estimate);
+
+    loop {
+        match write(&mut result) {
+            AzReturnCode::AzResultCoreErrorNotEnoughSpace => {
+                result = T::with_capacity(capacity_increase_policy(result.capacity()));
+            }
+            AzReturnCode::AzResultCoreOk => {
+                result.shrink_to_fit();
+                return Ok(result);
+            }
+            rc => {
+                return Err(rc);
+            }
         }
     }
 }
 
-pub enum TopicType {
-    C2D(ClientC2DRequest),
-    Method(ClientMethodRequest),
-    Unknown,
+// Runs one of the C SDK functions that write a null terminated string into a caller provided
+// buffer and returns the text it wrote
+pub(crate) fn write_c_string<F>(buffer: &mut [u8], write: F) -> Result<&str, AzReturnCode>
+where
+    F: FnOnce(*mut c_char, azsys::size_t, *mut azsys::size_t) -> azsys::az_result,
+{
+    // The C SDK requires room for at least the null terminator
+    if buffer.is_empty() {
+        return Err(AzReturnCode::AzResultCoreErrorNotEnoughSpace);
+    }
+
+    let mut len: azsys::size_t = 0;
+    let rc = write(buffer.as_mut_ptr() as *mut _, buffer.len() as azsys::size_t, &mut len);
+    AzReturnCode::from_i32(rc).into_result()?;
+
+    let buffer: &[u8] = buffer;
+    let written = buffer.get(..len as usize).ok_or(AzReturnCode::AzResultCoreErrorNotEnoughSpace)?;
+    Ok(str::from_utf8(written)?)
 }
 
-fn capacity_increase_policy(current: usize) -> usize {
-    let result = current + (current / 50);
-    result
+// Runs one of the C SDK functions that fill a span and returns the bytes it wrote
+pub(crate) fn write_span<F>(buffer: &mut [u8], write: F) -> Result<&[u8], AzReturnCode>
+where
+    F: FnOnce(azsys::az_span, *mut azsys::az_span) -> azsys::az_result,
+{
+    let mut out = AzSpan::empty().as_raw();
+    let rc = write(AzSpanMut::from(&mut buffer[..]).as_raw(), &mut out);
+    AzReturnCode::from_i32(rc).into_result()?;
+
+    let buffer: &[u8] = buffer;
+    let len = AzSpan::from(buffer).sub_span(out)?.len();
+    Ok(&buffer[..len])
 }
 
 impl HubClient {
@@ -90,22 +207,24 @@ impl HubClient {
         device_id: &str,
         options: Option<HubClientOptions>,
     ) -> Result<HubClient, AzReturnCode> {
-        let options_work: *const azsys::az_iot_hub_client_options;
-
-        match options {
-            Some(o) => options_work = &o.inner,
-            None => options_work = std::ptr::null(),
-        }
+        let options_work: *const azsys::az_iot_hub_client_options = match &options {
+            Some(o) => &o.inner,
+            None => core::ptr::null(),
+        };
 
+        // The C client only holds spans so it keeps copies of the strings they refer to
         let mut client: HubClient = HubClient::new_empty();
+        client.host_name = host_name.to_string();
+        client.device_id = device_id.to_string();
         let rc = unsafe {
             azsys::az_iot_hub_client_init(
                 &mut client.inner,
-                get_span_from_str(host_name),
-                get_span_from_str(device_id),
+                AzSpan::from(client.host_name.as_str()).as_raw(),
+                AzSpan::from(client.device_id.as_str()).as_raw(),
                 options_work,
             )
         };
+        client.options = options;
 
         if rc != azsys::az_result_core_AZ_OK {
             Err(AzReturnCode::from_i32(rc))
@@ -118,47 +237,64 @@ impl HubClient {
         HubClient {
             inner: azsys::az_iot_hub_client {
                 _internal: azsys::az_iot_hub_client__bindgen_ty_1 {
-                    iot_hub_hostname: get_empty_span(),
-                    device_id: get_empty_span(),
+                    iot_hub_hostname: AzSpan::empty().as_raw(),
+                    device_id: AzSpan::empty().as_raw(),
                     options: HubClientOptions::default_new().inner,
                 },
             },
+            host_name: String::new(),
+            device_id: String::new(),
+            options: Option::None,
         }
     }
 
-    pub fn get_client_id(&self) -> Result<String, AzReturnCode> {
-        let mut capacity: usize = 100;
-        let mut result = String::with_capacity(capacity);
+    pub fn get_host_name(&self) -> &str {
+        &self.host_name
+    }
 
-        loop {
-            let rc = self.ll_get_client_id(&mut result);
+    pub fn get_device_id(&self) -> &str {
+        &self.device_id
+    }
 
-            match rc {
-                AzReturnCode::AzResultCoreErrorNotEnoughSpace => {
-                    capacity = capacity_increase_policy(capacity);
-                    result = String::with_capacity(capacity);
-                    continue;
-                }
-                AzReturnCode::AzResultCoreOk => {
-                    result.shrink_to_fit();
-                    return Ok(result);
-                }
-                _ => {
-                    return Err(rc);
-                }
-            }
+    // Total length of the names that appear in client ids, user names and SAS tokens
+    fn identity_len(&self) -> usize {
+        self.host_name.len() + self.device_id.len() + self.get_module_id().map_or(0, str::len)
+    }
+
+    fn user_name_capacity(&self) -> usize {
+        let options_len = match &self.options {
+            Some(o) => o.get_model_id().map_or(0, url_encoded_len) + o.get_user_agent().map_or(0, url_encoded_len),
+            None => 0,
+        };
+        self.identity_len() + options_len + FIXED_TEXT_ESTIMATE
+    }
+
+    fn telemetry_topic_capacity(&self, message_properties: Option<&MessageProperties>) -> usize {
+        let properties_len = message_properties.map_or(0, |m| m.inner._internal.properties_written as usize);
+        self.identity_len() + properties_len + FIXED_TEXT_ESTIMATE
+    }
+
+    pub fn get_module_id(&self) -> Option<&str> {
+        match &self.options {
+            Some(o) => o.get_module_id(),
+            None => Option::None,
         }
     }
 
+    pub fn get_client_id(&self) -> Result<String, AzReturnCode> {
+        let capacity = self.identity_len() + FIXED_TEXT_ESTIMATE;
+        grow_until_fits(capacity, |result| self.ll_get_client_id(result))
+    }
+
     pub fn ll_get_client_id(&self, result: &mut String) -> AzReturnCode {
-        let mut len: u64 = 0;
-        let len_ptr: *mut u64 = &mut len;
+        let mut len: azsys::size_t = 0;
+        let len_ptr: *mut azsys::size_t = &mut len;
 
         let rc = unsafe {
             azsys::az_iot_hub_client_get_client_id(
                 &self.inner,
-                result.as_mut_vec().as_mut_ptr() as *mut i8,
-                result.capacity() as u64,
+                result.as_mut_vec().as_mut_ptr() as *mut _,
+                result.capacity() as azsys::size_t,
                 len_ptr,
             )
         };
@@ -170,39 +306,27 @@ impl HubClient {
         AzReturnCode::from_i32(rc)
     }
 
-    pub fn get_user_name(&self) -> Result<String, AzReturnCode> {
-        let mut capacity: usize = 100;
-        let mut result = String::with_capacity(capacity);
-
-        loop {
-            let rc = self.ll_get_user_name(&mut result);
+    /// Writes the client id into buffer without allocating.
+    pub fn get_client_id_into<'b>(&self, buffer: &'b mut [u8]) -> Result<&'b str, AzReturnCode> {
+        write_c_string(buffer, |ptr, size, len| unsafe {
+            azsys::az_iot_hub_client_get_client_id(&self.inner, ptr, size, len)
+        })
+    }
 
-            match rc {
-                AzReturnCode::AzResultCoreErrorNotEnoughSpace => {
-                    capacity = capacity_increase_policy(capacity);
-                    result = String::with_capacity(capacity);
-                    continue;
-                }
-                AzReturnCode::AzResultCoreOk => {
-                    result.shrink_to_fit();
-                    return Ok(result);
-                }
-                _ => {
-                    return Err(rc);
-                }
-            }
-        }
+    pub fn get_user_name(&self) -> Result<String, AzReturnCode> {
+        let capacity = self.user_name_capacity();
+        grow_until_fits(capacity, |result| self.ll_get_user_name(result))
     }
 
     pub fn ll_get_user_name(&self, result: &mut String) -> AzReturnCode {
-        let mut len: u64 = 0;
-        let len_ptr: *mut u64 = &mut len;
+        let mut len: azsys::size_t = 0;
+        let len_ptr: *mut azsys::size_t = &mut len;
 
         let rc = unsafe {
             azsys::az_iot_hub_client_get_user_name(
                 &self.inner,
-                result.as_mut_vec().as_mut_ptr() as *mut i8,
-                result.capacity() as u64,
+                result.as_mut_vec().as_mut_ptr() as *mut _,
+                result.capacity() as azsys::size_t,
                 len_ptr,
             )
         };
@@ -214,26 +338,42 @@ impl HubClient {
         AzReturnCode::from_i32(rc)
     }
 
+    pub fn get_user_name_into<'b>(&self, buffer: &'b mut [u8]) -> Result<&'b str, AzReturnCode> {
+        write_c_string(buffer, |ptr, size, len| unsafe {
+            azsys::az_iot_hub_client_get_user_name(&self.inner, ptr, size, len)
+        })
+    }
+
     pub fn get_c2d_subscribe_topic() -> &'static str {
         static AZ_IOT_HUB_CLIENT_C2D_SUBSCRIBE_TOPIC: &str = "devices/+/messages/devicebound/#";
         AZ_IOT_HUB_CLIENT_C2D_SUBSCRIBE_TOPIC
     }
 
     pub fn c2d_parse_received_topic(&self, topic: &str) -> Result<ClientC2DRequest, AzReturnCode> {
-        let mut result: ClientC2DRequest = ClientC2DRequest::new_empty();
+        let topic_span = AzSpan::from(topic);
+        let mut request = azsys::az_iot_hub_client_c2d_request {
+            properties: MessageProperties::new_empty(Vec::new()).inner,
+        };
         let rc = unsafe {
             azsys::az_iot_hub_client_c2d_parse_received_topic(
                 &self.inner,
-                get_span_from_str(topic),
-                &mut result.inner,
+                topic_span.as_raw(),
+                &mut request,
             )
         };
 
         if rc != azsys::az_result_core_AZ_OK {
-            Err(AzReturnCode::from_i32(rc))
-        } else {
-            Ok(result)
+            return Err(AzReturnCode::from_i32(rc));
         }
+
+        // Take a copy of the properties so the request does not borrow the topic
+        let properties = topic_span.sub_span(request.properties._internal.properties_buffer)?;
+        let props = MessageProperties::new(
+            properties.as_bytes().to_vec(),
+            request.properties._internal.properties_written,
+        )?;
+
+        Ok(ClientC2DRequest::from_message_properties(props))
     }
 
     pub fn get_method_subscribe_topic() -> &'static str {
@@ -241,60 +381,60 @@ impl HubClient {
         AZ_IOT_HUB_CLIENT_METHODS_SUBSCRIBE_TOPIC
     }
 
-    pub fn methods_parse_received_topic(
+    pub fn methods_parse_received_topic<'a>(
         &self,
-        topic: &str,
-    ) -> Result<ClientMethodRequest, AzReturnCode> {
-        let mut result: ClientMethodRequest = ClientMethodRequest::new_empty();
+        topic: &'a str,
+    ) -> Result<ClientMethodRequest<'a>, AzReturnCode> {
+        let topic_span = AzSpan::from(topic);
+        let mut request = azsys::az_iot_hub_client_method_request {
+            request_id: AzSpan::empty().as_raw(),
+            name: AzSpan::empty().as_raw(),
+        };
         let rc = unsafe {
             azsys::az_iot_hub_client_methods_parse_received_topic(
                 &self.inner,
-                get_span_from_str(topic),
-                &mut result.inner,
+                topic_span.as_raw(),
+                &mut request,
             )
         };
 
         if rc != azsys::az_result_core_AZ_OK {
-            Err(AzReturnCode::from_i32(rc))
-        } else {
-            Ok(result)
+            return Err(AzReturnCode::from_i32(rc));
         }
-    }
 
-    pub fn methods_response_get_publish_topic(&self, request_id: &str, status: u16) -> Result<String, AzReturnCode> {
-        let mut capacity: usize = 100;
-        let mut result = String::with_capacity(capacity);
-
-        loop {
-            let rc = self.ll_methods_response_get_publish_topic(&request_id, status, &mut result);
+        Ok(ClientMethodRequest {
+            request_id: topic_span.sub_span(request.request_id)?,
+            name: topic_span.sub_span(request.name)?,
+        })
+    }
 
-            match rc {
-                AzReturnCode::AzResultCoreErrorNotEnoughSpace => {
-                    capacity = capacity_increase_policy(capacity);
-                    result = String::with_capacity(capacity);
-                    continue;
-                }
-                AzReturnCode::AzResultCoreOk => {
-                    result.shrink_to_fit();
-                    return Ok(result);
-                }
-                _ => {
-                    return Err(rc);
-                }
-            }
-        }
+    /// status is an IotStatus, for example `IotStatus::Ok`, or a raw u16 status code.
+    pub fn methods_response_get_publish_topic(
+        &self,
+        request_id: &str,
+        status: impl Into<u16>,
+    ) -> Result<String, AzReturnCode> {
+        let status = status.into();
+        let capacity = request_id.len() + FIXED_TEXT_ESTIMATE;
+        grow_until_fits(capacity, |result| self.ll_methods_response_get_publish_topic(request_id, status, result))
     }
 
-    pub fn ll_methods_response_get_publish_topic(&self, request_id: &str, status: u16, result: &mut String) -> AzReturnCode {
-        let request_id_span = get_span_from_str(request_id);
-        let mut len: u64 = 0;
-        let len_ptr: *mut u64 = &mut len;
+    pub fn ll_methods_response_get_publish_topic(
+        &self,
+        request_id: &str,
+        status: impl Into<u16>,
+        result: &mut String,
+    ) -> AzReturnCode {
+        let status = status.into();
+        let request_id_span = AzSpan::from(request_id).as_raw();
+        let mut len: azsys::size_t = 0;
+        let len_ptr: *mut azsys::size_t = &mut len;
         let rc = unsafe { azsys::az_iot_hub_client_methods_response_get_publish_topic(
             &self.inner, 
             request_id_span, 
             status,                 
-            result.as_mut_vec().as_mut_ptr() as *mut i8,
-            result.capacity() as u64,
+            result.as_mut_vec().as_mut_ptr() as *mut _,
+            result.capacity() as azsys::size_t,
             len_ptr,
         ) };
 
@@ -305,6 +445,25 @@ impl HubClient {
         AzReturnCode::from_i32(rc)
     }
 
+    pub fn methods_response_get_publish_topic_into<'b>(
+        &self,
+        request_id: &str,
+        status: impl Into<u16>,
+        buffer: &'b mut [u8],
+    ) -> Result<&'b str, AzReturnCode> {
+        let status = status.into();
+        write_c_string(buffer, |ptr, size, len| unsafe {
+            azsys::az_iot_hub_client_methods_response_get_publish_topic(
+                &self.inner,
+                AzSpan::from(request_id).as_raw(),
+                status,
+                ptr,
+                size,
+                len,
+            )
+        })
+    }
+
     pub fn get_twin_respnse_subscribe_topic() -> &'static str {
         static AZ_IOT_HUB_CLIENT_TWIN_RESPONSE_SUBSCRIBE_TOPIC: &str = "$iothub/twin/res/#";
         AZ_IOT_HUB_CLIENT_TWIN_RESPONSE_SUBSCRIBE_TOPIC
@@ -316,7 +475,175 @@ impl HubClient {
         AZ_IOT_HUB_CLIENT_TWIN_PATCH_SUBSCRIBE_TOPIC
     }
 
-    pub fn get_topic_type(&self, topic: &str) -> Result<TopicType, AzReturnCode> {
+    pub fn twin_document_get_publish_topic(&self, request_id: &str) -> Result<String, AzReturnCode> {
+        let capacity = request_id.len() + FIXED_TEXT_ESTIMATE;
+        grow_until_fits(capacity, |result| self.ll_twin_document_get_publish_topic(request_id, result))
+    }
+
+    pub fn ll_twin_document_get_publish_topic(&self, request_id: &str, result: &mut String) -> AzReturnCode {
+        let mut len: azsys::size_t = 0;
+        let len_ptr: *mut azsys::size_t = &mut len;
+        let rc = unsafe {
+            azsys::az_iot_hub_client_twin_document_get_publish_topic(
+                &self.inner,
+                AzSpan::from(request_id).as_raw(),
+                result.as_mut_vec().as_mut_ptr() as *mut _,
+                result.capacity() as azsys::size_t,
+                len_ptr,
+            )
+        };
+
+        if rc == azsys::az_result_core_AZ_OK {
+            unsafe { result.as_mut_vec().set_len(len as usize) };
+        }
+
+        AzReturnCode::from_i32(rc)
+    }
+
+    pub fn twin_document_get_publish_topic_into<'b>(
+        &self,
+        request_id: &str,
+        buffer: &'b mut [u8],
+    ) -> Result<&'b str, AzReturnCode> {
+        write_c_string(buffer, |ptr, size, len| unsafe {
+            azsys::az_iot_hub_client_twin_document_get_publish_topic(
+                &self.inner,
+                AzSpan::from(request_id).as_raw(),
+                ptr,
+                size,
+                len,
+            )
+        })
+    }
+
+    pub fn twin_patch_get_publish_topic(&self, request_id: &str) -> Result<String, AzReturnCode> {
+        let capacity = request_id.len() + FIXED_TEXT_ESTIMATE;
+        grow_until_fits(capacity, |result| self.ll_twin_patch_get_publish_topic(request_id, result))
+    }
+
+    pub fn ll_twin_patch_get_publish_topic(&self, request_id: &str, result: &mut String) -> AzReturnCode {
+        let mut len: azsys::size_t = 0;
+        let len_ptr: *mut azsys::size_t = &mut len;
+        let rc = unsafe {
+            azsys::az_iot_hub_client_twin_patch_get_publish_topic(
+                &self.inner,
+                AzSpan::from(request_id).as_raw(),
+                result.as_mut_vec().as_mut_ptr() as *mut _,
+                result.capacity() as azsys::size_t,
+                len_ptr,
+            )
+        };
+
+        if rc == azsys::az_result_core_AZ_OK {
+            unsafe { result.as_mut_vec().set_len(len as usize) };
+        }
+
+        AzReturnCode::from_i32(rc)
+    }
+
+    pub fn twin_patch_get_publish_topic_into<'b>(
+        &self,
+        request_id: &str,
+        buffer: &'b mut [u8],
+    ) -> Result<&'b str, AzReturnCode> {
+        write_c_string(buffer, |ptr, size, len| unsafe {
+            azsys::az_iot_hub_client_twin_patch_get_publish_topic(
+                &self.inner,
+                AzSpan::from(request_id).as_raw(),
+                ptr,
+                size,
+                len,
+            )
+        })
+    }
+
+    pub fn twin_parse_received_topic<'a>(&self, topic: &'a str) -> Result<ClientTwinResponse<'a>, AzReturnCode> {
+        let topic_span = AzSpan::from(topic);
+        let mut response = azsys::az_iot_hub_client_twin_response {
+            response_type: 0,
+            status: 0,
+            request_id: AzSpan::empty().as_raw(),
+            version: AzSpan::empty().as_raw(),
+        };
+        let rc = unsafe {
+            azsys::az_iot_hub_client_twin_parse_received_topic(
+                &self.inner,
+                topic_span.as_raw(),
+                &mut response,
+            )
+        };
+
+        if rc != azsys::az_result_core_AZ_OK {
+            return Err(AzReturnCode::from_i32(rc));
+        }
+
+        let response_type = match response.response_type {
+            azsys::az_iot_hub_client_twin_response_type_AZ_IOT_HUB_CLIENT_TWIN_RESPONSE_TYPE_GET => {
+                TwinResponseType::Get
+            }
+            azsys::az_iot_hub_client_twin_response_type_AZ_IOT_HUB_CLIENT_TWIN_RESPONSE_TYPE_DESIRED_PROPERTIES => {
+                TwinResponseType::DesiredProperties
+            }
+            azsys::az_iot_hub_client_twin_response_type_AZ_IOT_HUB_CLIENT_TWIN_RESPONSE_TYPE_REPORTED_PROPERTIES => {
+                TwinResponseType::ReportedProperties
+            }
+            _ => TwinResponseType::Unknown,
+        };
+
+        Ok(ClientTwinResponse {
+            response_type,
+            status: response.status as u16,
+            request_id: topic_span.sub_span(response.request_id)?,
+            version: topic_span.sub_span(response.version)?,
+        })
+    }
+
+    pub fn get_module_input_subscribe_topic(&self) -> Result<String, AzReturnCode> {
+        match self.get_module_id() {
+            Some(module_id) => Ok(format!("devices/{}/modules/{}/inputs/#", self.get_device_id(), module_id)),
+            None => Err(AzReturnCode::AzResultCoreErrorNotSupported),
+        }
+    }
+
+    /// Edge modules receive routed messages on devices/{device}/modules/{module}/inputs/{name}/{properties}.
+    /// The embedded C SDK has no parser for these so the topic is matched against this client's identity here.
+    /// Like get_module_input_subscribe_topic this fails with AzResultCoreErrorNotSupported for a device client.
+    pub fn module_input_parse_received_topic(&self, topic: &str) -> Result<ClientModuleInputRequest, AzReturnCode> {
+        let module_id = match self.get_module_id() {
+            Some(m) => m,
+            None => return Err(AzReturnCode::AzResultCoreErrorNotSupported),
+        };
+        let remainder = topic
+            .strip_prefix("devices/")
+            .and_then(|t| t.strip_prefix(self.get_device_id()))
+            .and_then(|t| t.strip_prefix("/modules/"))
+            .and_then(|t| t.strip_prefix(module_id))
+            .and_then(|t| t.strip_prefix("/inputs/"))
+            .ok_or(AzReturnCode::AzResultIoTErrorTopicNoMatch)?;
+        let (input_name, properties) = match remainder.find('/') {
+            Some(i) => (&remainder[..i], &remainder[i + 1..]),
+            None => return Err(AzReturnCode::AzResultIoTErrorTopicNoMatch),
+        };
+
+        if input_name.is_empty() {
+            return Err(AzReturnCode::AzResultIoTErrorTopicNoMatch);
+        }
+
+        let props = MessageProperties::new(properties.as_bytes().to_vec(), properties.len() as i32)?;
+
+        Ok(ClientModuleInputRequest {
+            input_name: input_name.to_string(),
+            message_props: props,
+        })
+    }
+
+    /// Classifies a topic received as raw bytes, text that is not valid UTF-8 fails with
+    /// AzResultCoreErrorUnexpectedChar rather than being matched against any topic.
+    pub fn get_topic_type_from_bytes<'a>(&self, topic: &'a [u8]) -> Result<TopicType<'a>, AzReturnCode> {
+        self.get_topic_type(str::from_utf8(topic)?)
+    }
+
+    pub fn get_topic_type<'a>(&self, topic: &'a str) -> Result<TopicType<'a>, AzReturnCode> {
         match self.c2d_parse_received_topic(topic) {
             Ok(val) =>  { return Ok(TopicType::C2D(val)); },
             Err(rc) => {
@@ -335,6 +662,26 @@ impl HubClient {
             },
         }
 
+        match self.twin_parse_received_topic(topic) {
+            Ok(val) => { return Ok(TopicType::from_twin_response(val)); },
+            Err(rc) => {
+                if rc != AzReturnCode::AzResultIoTErrorTopicNoMatch {
+                    return Err(rc);
+                }
+            },
+        }
+
+        if self.get_module_id().is_some() {
+            match self.module_input_parse_received_topic(topic) {
+                Ok(val) => { return Ok(TopicType::ModuleInput(val)); },
+                Err(rc) => {
+                    if rc != AzReturnCode::AzResultIoTErrorTopicNoMatch {
+                        return Err(rc);
+                    }
+                },
+            }
+        }
+
         Ok(TopicType::Unknown)
     }
 
@@ -342,27 +689,8 @@ impl HubClient {
         &self,
         message_properties: Option<MessageProperties>,
     ) -> Result<String, AzReturnCode> {
-        let mut capacity: usize = 100;
-        let mut result = String::with_capacity(capacity);
-
-        loop {
-            let rc = self.ll_get_telemetry_publish_topic(&message_properties, &mut result);
-
-            match rc {
-                AzReturnCode::AzResultCoreErrorNotEnoughSpace => {
-                    capacity = capacity_increase_policy(capacity);
-                    result = String::with_capacity(capacity);
-                    continue;
-                }
-                AzReturnCode::AzResultCoreOk => {
-                    result.shrink_to_fit();
-                    return Ok(result);
-                }
-                _ => {
-                    return Err(rc);
-                }
-            }
-        }
+        let capacity = self.telemetry_topic_capacity(message_properties.as_ref());
+        grow_until_fits(capacity, |result| self.ll_get_telemetry_publish_topic(&message_properties, result))
     }
 
     pub fn ll_get_telemetry_publish_topic(
@@ -370,18 +698,18 @@ impl HubClient {
         message_properties: &Option<MessageProperties>,
         result: &mut String,
     ) -> AzReturnCode {
-        let mut len: u64 = 0;
-        let len_ptr: *mut u64 = &mut len;
+        let mut len: azsys::size_t = 0;
+        let len_ptr: *mut azsys::size_t = &mut len;
         let m_prop_work: *const azsys::az_iot_message_properties = match message_properties {
             Some(m) => &m.inner,
-            None => std::ptr::null(),
+            None => core::ptr::null(),
         };
         let rc = unsafe {
             azsys::az_iot_hub_client_telemetry_get_publish_topic(
                 &self.inner,
                 m_prop_work,
-                result.as_mut_vec().as_mut_ptr() as *mut i8,
-                result.capacity() as u64,
+                result.as_mut_vec().as_mut_ptr() as *mut _,
+                result.capacity() as azsys::size_t,
                 len_ptr,
             )
         };
@@ -393,80 +721,89 @@ impl HubClient {
         AzReturnCode::from_i32(rc)
     }
 
-    pub fn get_sas_signature(&self, ttl: u64) -> Result<Vec<u8>, AzReturnCode> {
-        let mut capacity: usize = 200;
-        let mut result: Vec<u8> = Vec::with_capacity(capacity);
+    pub fn get_telemetry_publish_topic_into<'b>(
+        &self,
+        message_properties: Option<&MessageProperties>,
+        buffer: &'b mut [u8],
+    ) -> Result<&'b str, AzReturnCode> {
+        let m_prop_work: *const azsys::az_iot_message_properties = match message_properties {
+            Some(m) => &m.inner,
+            None => core::ptr::null(),
+        };
 
-        loop {
-            let rc = self.ll_get_sas_signature(ttl, &mut result);
+        write_c_string(buffer, |ptr, size, len| unsafe {
+            azsys::az_iot_hub_client_telemetry_get_publish_topic(&self.inner, m_prop_work, ptr, size, len)
+        })
+    }
 
-            match rc {
-                AzReturnCode::AzResultCoreErrorNotEnoughSpace => {
-                    capacity = capacity_increase_policy(capacity);
-                    result = Vec::with_capacity(capacity);
-                    continue;
-                }
-                AzReturnCode::AzResultCoreOk => {
-                    result.shrink_to_fit();
-                    return Ok(result);
-                }
-                _ => {
-                    return Err(rc);
-                }
-            }
-        }
+    pub fn get_sas_signature(&self, ttl: u64) -> Result<Vec<u8>, AzReturnCode> {
+        let capacity = self.identity_len() + FIXED_TEXT_ESTIMATE;
+        grow_until_fits(capacity, |result| self.ll_get_sas_signature(ttl, result))
     }
 
     pub fn ll_get_sas_signature(&self, ttl: u64, result: &mut Vec<u8>) -> AzReturnCode {
-        let result_span = get_span_from_vector(&result);
-        let mut work = get_empty_span();
+        // Let the C SDK write into the whole allocation then trim to what it wrote
+        let capacity = result.capacity();
+        result.clear();
+        result.resize(capacity, 0);
+        let mut work = AzSpan::empty().as_raw();
         let rc = unsafe {
-            azsys::az_iot_hub_client_sas_get_signature(&self.inner, ttl, result_span, &mut work)
+            azsys::az_iot_hub_client_sas_get_signature(
+                &self.inner,
+                ttl,
+                AzSpanMut::from(result.as_mut_slice()).as_raw(),
+                &mut work,
+            )
         };
 
-        if rc == azsys::az_result_core_AZ_OK {
-            unsafe { result.set_len(get_span_size(&work) as usize) };
-        }
+        let written = if rc == azsys::az_result_core_AZ_OK {
+            match AzSpan::from(result.as_slice()).sub_span(work) {
+                Ok(span) => span.len(),
+                Err(e) => {
+                    result.clear();
+                    return e;
+                }
+            }
+        } else {
+            0
+        };
+        result.truncate(written);
 
         AzReturnCode::from_i32(rc)
     }
 
-    pub fn get_sas_password(&self, ttl: u64, sas: &str) -> Result<String, AzReturnCode> {
-        let mut capacity: usize = 300;
-        let mut result = String::with_capacity(capacity);
-
-        loop {
-            let rc = self.ll_get_sas_password(ttl, sas, &mut result);
+    pub fn get_sas_signature_into<'b>(&self, ttl: u64, buffer: &'b mut [u8]) -> Result<&'b [u8], AzReturnCode> {
+        write_span(buffer, |span, out| unsafe {
+            azsys::az_iot_hub_client_sas_get_signature(&self.inner, ttl, span, out)
+        })
+    }
 
-            match rc {
-                AzReturnCode::AzResultCoreErrorNotEnoughSpace => {
-                    capacity = capacity_increase_policy(capacity);
-                    result = String::with_capacity(capacity);
-                    continue;
-                }
-                AzReturnCode::AzResultCoreOk => {
-                    result.shrink_to_fit();
-                    return Ok(result);
-                }
-                _ => {
-                    return Err(rc);
-                }
-            }
-        }
+    pub fn get_sas_password(&self, ttl: u64, sas: &str, key_name: Option<&str>) -> Result<String, AzReturnCode> {
+        let capacity = self.identity_len() + url_encoded_len(sas) + key_name.map_or(0, str::len) + FIXED_TEXT_ESTIMATE;
+        grow_until_fits(capacity, |result| self.ll_get_sas_password(ttl, sas, key_name, result))
     }
 
-    pub fn ll_get_sas_password(&self, ttl: u64, sas: &str, result: &mut String) -> AzReturnCode {
-        // TODO: Add key_name option
-        let mut len: u64 = 0;
-        let len_ptr: *mut u64 = &mut len;
+    pub fn ll_get_sas_password(
+        &self,
+        ttl: u64,
+        sas: &str,
+        key_name: Option<&str>,
+        result: &mut String,
+    ) -> AzReturnCode {
+        let key_name_span = match key_name {
+            Some(k) => AzSpan::from(k).as_raw(),
+            None => AzSpan::empty().as_raw(),
+        };
+        let mut len: azsys::size_t = 0;
+        let len_ptr: *mut azsys::size_t = &mut len;
         let rc = unsafe {
             azsys::az_iot_hub_client_sas_get_password(
                 &self.inner,
                 ttl,
-                get_span_from_str(sas),
-                get_empty_span(),
-                result.as_mut_ptr() as *mut i8,
-                result.capacity() as u64,
+                AzSpan::from(sas).as_raw(),
+                key_name_span,
+                result.as_mut_ptr() as *mut _,
+                result.capacity() as azsys::size_t,
                 len_ptr,
             )
         };
@@ -478,6 +815,56 @@ impl HubClient {
         AzReturnCode::from_i32(rc)
     }
 
+    pub fn get_sas_password_into<'b>(
+        &self,
+        ttl: u64,
+        sas: &str,
+        key_name: Option<&str>,
+        buffer: &'b mut [u8],
+    ) -> Result<&'b str, AzReturnCode> {
+        let key_name_span = match key_name {
+            Some(k) => AzSpan::from(k).as_raw(),
+            None => AzSpan::empty().as_raw(),
+        };
+
+        write_c_string(buffer, |ptr, size, len| unsafe {
+            azsys::az_iot_hub_client_sas_get_password(
+                &self.inner,
+                ttl,
+                AzSpan::from(sas).as_raw(),
+                key_name_span,
+                ptr,
+                size,
+                len,
+            )
+        })
+    }
+
+    /// Builds the complete MQTT password from the device's base64 encoded symmetric key.
+    /// The signature is signed with HMAC-SHA256 and base64 encoded; the C SDK URL encodes it
+    /// when building the password.
+    #[cfg(feature = "sas-token")]
+    pub fn generate_sas_password(
+        &self,
+        device_key: &str,
+        ttl: u64,
+        key_name: Option<&str>,
+    ) -> Result<String, AzReturnCode> {
+        let key = match base64::decode(device_key) {
+            Ok(k) => k,
+            Err(_) => return Err(AzReturnCode::AzResultCoreErrorArg),
+        };
+        let signature = self.get_sas_signature(ttl)?;
+        let mut mac = match Hmac::<Sha256>::new_from_slice(&key) {
+            Ok(m) => m,
+            Err(_) => return Err(AzReturnCode::AzResultCoreErrorArg),
+        };
+        mac.update(&signature);
+        let sas = base64::encode(mac.finalize().into_bytes());
+
+        self.get_sas_password(ttl, &sas, key_name)
+    }
+
     pub fn calculate_retry_delay(
         operation_msec: i32, 
         attempt: i16, 
@@ -491,42 +878,185 @@ impl HubClient {
 
 pub struct HubClientOptions {
     inner: azsys::az_iot_hub_client_options,
+    module_id: Option<String>,
+    model_id: Option<String>,
+    user_agent: Option<String>,
 }
 
 impl HubClientOptions {
     pub fn default_new() -> HubClientOptions {
         HubClientOptions {
             inner: unsafe { azsys::az_iot_hub_client_options_default() },
+            module_id: Option::None,
+            model_id: Option::None,
+            user_agent: Option::None,
         }
     }
-}
 
-pub struct MessagePropertiesBuilder {
-    props: Vec<u8>,
-}
+    pub fn module_id(mut self, module_id: &str) -> HubClientOptions {
+        let module_id = module_id.to_string();
+        self.inner.module_id = AzSpan::from(module_id.as_str()).as_raw();
+        self.module_id = Option::Some(module_id);
+        self
+    }
 
-impl MessagePropertiesBuilder {
-    pub fn new() -> MessagePropertiesBuilder {
-        MessagePropertiesBuilder::with_capacity(200)
+    pub fn model_id(mut self, model_id: &str) -> HubClientOptions {
+        let model_id = model_id.to_string();
+        self.inner.model_id = AzSpan::from(model_id.as_str()).as_raw();
+        self.model_id = Option::Some(model_id);
+        self
     }
 
-    pub fn with_capacity(capacity: usize) -> MessagePropertiesBuilder {
-        MessagePropertiesBuilder {
-            props: Vec::with_capacity(capacity),
+    pub fn user_agent(mut self, user_agent: &str) -> HubClientOptions {
+        let user_agent = user_agent.to_string();
+        self.inner.user_agent = AzSpan::from(user_agent.as_str()).as_raw();
+        self.user_agent = Option::Some(user_agent);
+        self
+    }
+
+    pub fn get_module_id(&self) -> Option<&str> {
+        self.module_id.as_deref()
+    }
+
+    pub fn get_model_id(&self) -> Option<&str> {
+        self.model_id.as_deref()
+    }
+
+    pub fn get_user_agent(&self) -> Option<&str> {
+        self.user_agent.as_deref()
+    }
+}
+
+// The C SDK's AZ_IOT_MESSAGE_PROPERTIES_* names, already percent encoded as they appear in topics
+const MESSAGE_ID_PROPERTY: &str = "%24.mid";
+const CORRELATION_ID_PROPERTY: &str = "%24.cid";
+const CONTENT_TYPE_PROPERTY: &str = "%24.ct";
+const CONTENT_ENCODING_PROPERTY: &str = "%24.ce";
+const USER_ID_PROPERTY: &str = "%24.uid";
+const CREATE_TIME_PROPERTY: &str = "iothub-creation-time-utc";
+const COMPONENT_NAME_PROPERTY: &str = "%24.sub";
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+// Percent encodes everything but the RFC 3986 unreserved characters so that '&', '=' and '/'
+// in keys and values cannot change the structure of the topic
+fn url_encode(value: &str) -> Cow<'_, str> {
+    fn is_unreserved(c: u8) -> bool {
+        c.is_ascii_alphanumeric() || c == b'-' || c == b'_' || c == b'.' || c == b'~'
+    }
+
+    if value.bytes().all(is_unreserved) {
+        return Cow::Borrowed(value);
+    }
+
+    let mut result = String::with_capacity(value.len() * 3);
+
+    for c in value.bytes() {
+        if is_unreserved(c) {
+            result.push(c as char);
+        } else {
+            result.push('%');
+            result.push(HEX_DIGITS[(c >> 4) as usize] as char);
+            result.push(HEX_DIGITS[(c & 0x0F) as usize] as char);
+        }
+    }
+
+    Cow::Owned(result)
+}
+
+fn url_decode(value: &[u8]) -> Result<Cow<'_, str>, AzReturnCode> {
+    if !value.contains(&b'%') {
+        return Ok(Cow::Borrowed(str::from_utf8(value)?));
+    }
+
+    let mut result = Vec::with_capacity(value.len());
+    let mut i = 0;
+
+    while i < value.len() {
+        if value[i] == b'%' {
+            let hex = value.get(i + 1..i + 3).ok_or(AzReturnCode::AzResultCoreErrorUnexpectedEnd)?;
+            result.push(hex_digit(hex[0])? << 4 | hex_digit(hex[1])?);
+            i += 3;
+        } else {
+            result.push(value[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(result)
+        .map(Cow::Owned)
+        .map_err(|_| AzReturnCode::AzResultCoreErrorUnexpectedChar)
+}
+
+// Unlike u8::from_str_radix this does not accept a leading sign
+fn hex_digit(c: u8) -> Result<u8, AzReturnCode> {
+    (c as char)
+        .to_digit(16)
+        .map(|d| d as u8)
+        .ok_or(AzReturnCode::AzResultCoreErrorUnexpectedChar)
+}
+
+pub struct MessagePropertiesBuilder {
+    props: Vec<u8>,
+}
+
+impl MessagePropertiesBuilder {
+    pub fn new() -> MessagePropertiesBuilder {
+        MessagePropertiesBuilder::with_capacity(200)
+    }
+
+    pub fn with_capacity(capacity: usize) -> MessagePropertiesBuilder {
+        MessagePropertiesBuilder {
+            props: Vec::with_capacity(capacity),
         }
     }
 
-    pub fn add(mut self, keyword: &str, value: &str) -> MessagePropertiesBuilder {
-        if self.props.len() != 0 {
-            self.props.push('&' as u8);
+    /// Adds a property, keyword and value are percent encoded as required.
+    pub fn add(self, keyword: &str, value: &str) -> MessagePropertiesBuilder {
+        self.add_encoded(&url_encode(keyword), value)
+    }
+
+    // Adds a property whose keyword is already percent encoded
+    fn add_encoded(mut self, keyword: &str, value: &str) -> MessagePropertiesBuilder {
+        if !self.props.is_empty() {
+            self.props.push(b'&');
         }
 
-        self.props.append(&mut keyword.as_bytes().to_vec().as_mut_slice().to_vec());
-        self.props.push('=' as u8);
-        self.props.append(&mut value.as_bytes().to_vec().as_mut_slice().to_vec());
+        self.props.extend_from_slice(keyword.as_bytes());
+        self.props.push(b'=');
+        self.props.extend_from_slice(url_encode(value).as_bytes());
         self
     }
 
+    pub fn content_type(self, value: &str) -> MessagePropertiesBuilder {
+        self.add_encoded(CONTENT_TYPE_PROPERTY, value)
+    }
+
+    pub fn content_encoding(self, value: &str) -> MessagePropertiesBuilder {
+        self.add_encoded(CONTENT_ENCODING_PROPERTY, value)
+    }
+
+    pub fn message_id(self, value: &str) -> MessagePropertiesBuilder {
+        self.add_encoded(MESSAGE_ID_PROPERTY, value)
+    }
+
+    pub fn correlation_id(self, value: &str) -> MessagePropertiesBuilder {
+        self.add_encoded(CORRELATION_ID_PROPERTY, value)
+    }
+
+    pub fn user_id(self, value: &str) -> MessagePropertiesBuilder {
+        self.add_encoded(USER_ID_PROPERTY, value)
+    }
+
+    pub fn component_name(self, value: &str) -> MessagePropertiesBuilder {
+        self.add_encoded(COMPONENT_NAME_PROPERTY, value)
+    }
+
+    /// Sets the creation time, an ISO 8601 UTC timestamp such as 2021-03-01T12:00:00.000Z.
+    pub fn creation_time(self, value: &str) -> MessagePropertiesBuilder {
+        self.add_encoded(CREATE_TIME_PROPERTY, value)
+    }
+
     pub fn finialize(self) -> Result<MessageProperties, AzReturnCode> {
         let len = self.props.len() as i32;
         MessageProperties::new(self.props, len)
@@ -539,12 +1069,16 @@ pub struct MessageProperties {
 }
 
 impl MessageProperties {
-    pub fn new(buffer: Vec<u8>, written_length: i32) -> Result<MessageProperties, AzReturnCode> {
+    pub fn new(mut buffer: Vec<u8>, written_length: i32) -> Result<MessageProperties, AzReturnCode> {
+        // The C SDK appends anywhere in the allocation so make all of it addressable
+        let capacity = buffer.capacity();
+        buffer.resize(capacity, 0);
         let mut message_properties = MessageProperties::new_empty(buffer);
+        let props_span = AzSpanMut::from(message_properties.props.as_mut_slice()).as_raw();
         let rc = unsafe {
             azsys::az_iot_message_properties_init(
                 &mut message_properties.inner,
-                message_properties.get_props_span(),
+                props_span,
                 written_length,
             )
         };
@@ -561,7 +1095,7 @@ impl MessageProperties {
             props: buffer,
             inner: azsys::az_iot_message_properties {
                 _internal: azsys::az_iot_message_properties__bindgen_ty_1 {
-                    properties_buffer: get_empty_span(),
+                    properties_buffer: AzSpan::empty().as_raw(),
                     properties_written: 0,
                     current_property_index: 0,
                 },
@@ -571,12 +1105,18 @@ impl MessageProperties {
         message_properties
     }
 
+    /// Appends a property, k and v are percent encoded as required.
     pub fn append(&mut self, k: &str, v: &str) -> Result<&mut MessageProperties, AzReturnCode> {
+        // The C SDK requires a non empty name and value
+        if k.is_empty() || v.is_empty() {
+            return Err(AzReturnCode::AzResultCoreErrorArg);
+        }
+
         let rc = unsafe {
             azsys::az_iot_message_properties_append(
                 &mut self.inner,
-                get_span_from_str(k),
-                get_span_from_str(v),
+                AzSpan::from(url_encode(k).as_ref()).as_raw(),
+                AzSpan::from(url_encode(v).as_ref()).as_raw(),
             )
         };
 
@@ -587,149 +1127,382 @@ impl MessageProperties {
         }
     }
 
-    pub fn find(&mut self, k: &str) -> Result<&str, AzReturnCode> {
-        let mut out = get_empty_span();
-        let rc = unsafe {
-            azsys::az_iot_message_properties_find(&mut self.inner, get_span_from_str(k), &mut out)
-        };
+    /// Returns the decoded value of the property named k.
+    pub fn find(&self, k: &str) -> Result<Cow<'_, str>, AzReturnCode> {
+        let value = self.find_span(k)?;
+        url_decode(value.as_bytes())
+    }
+
+    /// Returns Ok(None) when k is not present rather than AzResultCoreErrorItemNotFound.
+    pub fn get(&self, k: &str) -> Result<Option<Cow<'_, str>>, AzReturnCode> {
+        self.get_encoded(&url_encode(k))
+    }
+
+    // As get for a name that is already percent encoded
+    fn get_encoded(&self, k: &str) -> Result<Option<Cow<'_, str>>, AzReturnCode> {
+        match self.find_encoded_span(k) {
+            Ok(value) => Ok(Option::Some(url_decode(value.as_bytes())?)),
+            Err(AzReturnCode::AzResultCoreErrorItemNotFound) => Ok(Option::None),
+            Err(rc) => Err(rc),
+        }
+    }
+
+    /// True when get would return a value for k.
+    pub fn contains_key(&self, k: &str) -> bool {
+        matches!(self.get(k), Ok(Option::Some(_)))
+    }
+
+    pub fn get_content_type(&self) -> Result<Option<Cow<'_, str>>, AzReturnCode> {
+        self.get_encoded(CONTENT_TYPE_PROPERTY)
+    }
+
+    pub fn get_content_encoding(&self) -> Result<Option<Cow<'_, str>>, AzReturnCode> {
+        self.get_encoded(CONTENT_ENCODING_PROPERTY)
+    }
+
+    pub fn get_message_id(&self) -> Result<Option<Cow<'_, str>>, AzReturnCode> {
+        self.get_encoded(MESSAGE_ID_PROPERTY)
+    }
+
+    pub fn get_correlation_id(&self) -> Result<Option<Cow<'_, str>>, AzReturnCode> {
+        self.get_encoded(CORRELATION_ID_PROPERTY)
+    }
+
+    pub fn get_user_id(&self) -> Result<Option<Cow<'_, str>>, AzReturnCode> {
+        self.get_encoded(USER_ID_PROPERTY)
+    }
+
+    pub fn get_component_name(&self) -> Result<Option<Cow<'_, str>>, AzReturnCode> {
+        self.get_encoded(COMPONENT_NAME_PROPERTY)
+    }
+
+    pub fn get_creation_time(&self) -> Result<Option<Cow<'_, str>>, AzReturnCode> {
+        self.get_encoded(CREATE_TIME_PROPERTY)
+    }
+
+    /// Number of properties that decode successfully, which are the ones get can find.
+    pub fn len(&self) -> usize {
+        self.iter().filter(Result::is_ok).count()
+    }
+
+    /// True when len is zero, so properties that cannot be decoded are not counted either.
+    pub fn is_empty(&self) -> bool {
+        !self.iter().any(|property| property.is_ok())
+    }
+
+    // Whether the C state holds any bytes at all, decodable or not
+    fn is_raw_empty(&self) -> bool {
+        self.inner._internal.properties_written == 0
+    }
+
+    /// Iterates over the decoded properties from the start each time it is called. A pair that
+    /// cannot be decoded is returned as an error without ending the iteration.
+    pub fn iter(&self) -> MessagePropertiesIter<'_> {
+        let mut inner = self.inner;
+        inner._internal.current_property_index = 0;
+
+        MessagePropertiesIter {
+            inner,
+            props: AzSpan::from(self.props.as_slice()),
+            done: self.is_raw_empty(),
+        }
+    }
+
+    pub fn into_array(&self) -> Result<Vec<MessageProperty<'_>>, AzReturnCode> {
+        self.iter().collect()
+    }
+
+    // Searches a copy of the C state so that lookups do not need &mut self
+    fn find_span(&self, k: &str) -> Result<AzSpan<'_>, AzReturnCode> {
+        self.find_encoded_span(&url_encode(k))
+    }
+
+    fn find_encoded_span(&self, k: &str) -> Result<AzSpan<'_>, AzReturnCode> {
+        // The C SDK requires a non empty name
+        if k.is_empty() || self.is_raw_empty() {
+            return Err(AzReturnCode::AzResultCoreErrorItemNotFound);
+        }
+
+        let mut inner = self.inner;
+        let mut out = AzSpan::empty().as_raw();
+        let rc = unsafe { azsys::az_iot_message_properties_find(&mut inner, AzSpan::from(k).as_raw(), &mut out) };
 
         if rc != azsys::az_result_core_AZ_OK {
             Err(AzReturnCode::from_i32(rc))
         } else {
-            let slice =
-                unsafe { slice::from_raw_parts(out._internal.ptr, out._internal.size as usize) };
-            // let r = unsafe {
-            //     let slice = slice::from_raw_parts(out._internal.ptr, out._internal.size as usize);
-            //     str::from_utf8(slice)
-            // };
-            Ok(str::from_utf8(slice).expect("Value is not in UTF8"))
-        }
-    }
-
-    pub fn into_array(&mut self) -> Result<Vec<(&str, &str)>, AzReturnCode> {
-        let mut out: Vec<(&str, &str)> = Vec::new();
-        let mut k = get_empty_span();
-        let mut v = get_empty_span();
-
-        loop {
-            let rc = unsafe {
-                azsys::az_iot_message_properties_next(&mut self.inner, &mut k, &mut v)
-                    as ::std::os::raw::c_int
-            };
-
-            if rc == azsys::az_result_core_AZ_OK {
-                let slicek =
-                    unsafe { slice::from_raw_parts(k._internal.ptr, k._internal.size as usize) };
-                let slicev =
-                    unsafe { slice::from_raw_parts(v._internal.ptr, v._internal.size as usize) };
-                let ks = str::from_utf8(slicek).expect("keyword is not in UTF8");
-                let vs = str::from_utf8(slicev).expect("Value is not in UTF8");
-                out.push((ks, vs));
-            } else if rc == azsys::az_result_iot_AZ_ERROR_IOT_END_OF_PROPERTIES {
-                break;
-            } else {
-                return Err(AzReturnCode::from_i32(rc));
-            }
+            AzSpan::from(self.props.as_slice()).sub_span(out)
         }
+    }
 
-        Ok(out)
+    /// Returns an independent copy that shares no memory with this instance.
+    fn duplicate(&self) -> MessageProperties {
+        let mut result = MessageProperties::new_empty(self.props.clone());
+        result.inner._internal.properties_buffer = AzSpanMut::from(result.props.as_mut_slice()).as_raw();
+        result.inner._internal.properties_written = self.inner._internal.properties_written;
+        result
     }
+}
 
-    fn get_props_span(&self) -> azsys::az_span {
-        get_span_from_vector(&self.props)
+/// A decoded name and value pair, borrowed from the properties unless decoding was required.
+pub type MessageProperty<'a> = (Cow<'a, str>, Cow<'a, str>);
+
+pub struct MessagePropertiesIter<'a> {
+    inner: azsys::az_iot_message_properties,
+    props: AzSpan<'a>,
+    done: bool,
+}
+
+impl<'a> Iterator for MessagePropertiesIter<'a> {
+    type Item = Result<MessageProperty<'a>, AzReturnCode>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return Option::None;
+        }
+
+        let mut k = AzSpan::empty().as_raw();
+        let mut v = AzSpan::empty().as_raw();
+        let rc = unsafe { azsys::az_iot_message_properties_next(&mut self.inner, &mut k, &mut v) };
+
+        if rc == azsys::az_result_iot_AZ_ERROR_IOT_END_OF_PROPERTIES {
+            self.done = true;
+            return Option::None;
+        }
+
+        let result = if rc != azsys::az_result_core_AZ_OK {
+            Err(AzReturnCode::from_i32(rc))
+        } else {
+            self.props.sub_span(k).and_then(|k| self.props.sub_span(v).map(|v| (k, v)))
+        };
+
+        match result {
+            Ok((k, v)) => match (url_decode(k.as_bytes()), url_decode(v.as_bytes())) {
+                (Ok(k), Ok(v)) => Option::Some(Ok((k, v))),
+                (Err(rc), _) | (_, Err(rc)) => Option::Some(Err(rc)),
+            },
+            Err(rc) => {
+                self.done = true;
+                Option::Some(Err(rc))
+            }
+        }
     }
+}
+
+impl<'a> IntoIterator for &'a MessageProperties {
+    type Item = Result<MessageProperty<'a>, AzReturnCode>;
+    type IntoIter = MessagePropertiesIter<'a>;
 
-    pub(crate) fn get_inner(&self) -> azsys::az_iot_message_properties {
-        self.inner
+    fn into_iter(self) -> MessagePropertiesIter<'a> {
+        self.iter()
     }
 }
 
 pub struct ClientC2DRequest {
     message_props: MessageProperties,
-    inner: azsys::az_iot_hub_client_c2d_request,
 }
 
 impl ClientC2DRequest {
     pub fn new_empty() -> ClientC2DRequest {
-        let null_vec: Vec<u8> = Vec::new();
         ClientC2DRequest {
-            message_props: MessageProperties::new(null_vec, 0).unwrap(),
-            inner: azsys::az_iot_hub_client_c2d_request {
-                properties: azsys::az_iot_message_properties {
-                    _internal: azsys::az_iot_message_properties__bindgen_ty_1 {
-                        properties_buffer: get_empty_span(),
-                        properties_written: 0,
-                        current_property_index: 0,
-                    },
-                },
-            },
+            message_props: MessageProperties::new_empty(Vec::new()),
         }
     }
 
     pub fn from_message_properties(props: MessageProperties) -> ClientC2DRequest {
-        let mut result = ClientC2DRequest {
+        ClientC2DRequest {
             message_props: props,
-            inner: azsys::az_iot_hub_client_c2d_request {
-                properties: azsys::az_iot_message_properties {
-                    _internal: azsys::az_iot_message_properties__bindgen_ty_1 {
-                        properties_buffer: get_empty_span(),
-                        properties_written: 0,
-                        current_property_index: 0,
-                    },
-                },
-            }
-        };
+        }
+    }
 
-        result.inner.properties = result.message_props.get_inner();
-        result
+    pub fn get_message_properties(&self) -> MessageProperties {
+        self.message_props.duplicate()
+    }
+}
+
+pub struct ClientModuleInputRequest {
+    input_name: String,
+    message_props: MessageProperties,
+}
+
+impl ClientModuleInputRequest {
+    pub fn get_input_name(&self) -> &str {
+        &self.input_name
     }
 
     pub fn get_message_properties(&self) -> MessageProperties {
-        MessageProperties {
-            props: Vec::new(),
-            inner: azsys::az_iot_message_properties {
-                _internal: azsys::az_iot_message_properties__bindgen_ty_1 {
-                    properties_buffer: self.inner.properties._internal.properties_buffer,
-                    properties_written: self.inner.properties._internal.properties_written,
-                    current_property_index: self.inner.properties._internal.current_property_index,
-                },
-            },
-        }
+        self.message_props.duplicate()
     }
 }
 
-pub struct ClientMethodRequest {
-    inner: azsys::az_iot_hub_client_method_request,
+pub struct ClientMethodRequest<'a> {
+    request_id: AzSpan<'a>,
+    name: AzSpan<'a>,
 }
 
-impl ClientMethodRequest {
-    pub fn new_empty() -> ClientMethodRequest {
+impl<'a> ClientMethodRequest<'a> {
+    pub fn new_empty() -> ClientMethodRequest<'a> {
         ClientMethodRequest {
-            inner: azsys::az_iot_hub_client_method_request {
-                request_id: get_empty_span(),
-                name: get_empty_span(),
-            },
+            request_id: AzSpan::empty(),
+            name: AzSpan::empty(),
         }
     }
 
-    pub fn get_request_id(&self) -> &str {
-        let slice = unsafe {
-            slice::from_raw_parts(
-                get_span_ptr(&self.inner.request_id),
-                get_span_size(&self.inner.request_id) as usize,
-            )
-        };
-        str::from_utf8(&slice).expect("Request Id contains unprintable characters")
+    pub fn get_request_id(&self) -> Result<&'a str, AzReturnCode> {
+        Ok(self.request_id.as_str()?)
     }
 
-    pub fn get_name(&self) -> &str {
-        let slice = unsafe {
-            slice::from_raw_parts(
-                get_span_ptr(&self.inner.name),
-                get_span_size(&self.inner.name) as usize,
-            )
-        };
-        str::from_utf8(&slice).expect("Request Id contains unprintable characters")
+    pub fn get_request_id_bytes(&self) -> &'a [u8] {
+        self.request_id.as_bytes()
+    }
+
+    pub fn get_name(&self) -> Result<&'a str, AzReturnCode> {
+        Ok(self.name.as_str()?)
+    }
+
+    pub fn get_name_bytes(&self) -> &'a [u8] {
+        self.name.as_bytes()
+    }
+}
+
+/// Status codes used by IoT Hub and DPS in method, twin and registration responses.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum IotStatus {
+    Ok,
+    Accepted,
+    NoContent,
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    NotAllowed,
+    Conflict,
+    PreconditionFailed,
+    RequestTooLarge,
+    UnsupportedType,
+    Throttled,
+    ClientClosed,
+    ServerError,
+    BadGateway,
+    ServiceUnavailable,
+    Timeout,
+    /// A status not known to this crate
+    Unknown(u16),
+}
+
+static IOT_STATUSES: [(IotStatus, azsys::az_iot_status); 18] = [
+    (IotStatus::Ok, azsys::az_iot_status_AZ_IOT_STATUS_OK),
+    (IotStatus::Accepted, azsys::az_iot_status_AZ_IOT_STATUS_ACCEPTED),
+    (IotStatus::NoContent, azsys::az_iot_status_AZ_IOT_STATUS_NO_CONTENT),
+    (IotStatus::BadRequest, azsys::az_iot_status_AZ_IOT_STATUS_BAD_REQUEST),
+    (IotStatus::Unauthorized, azsys::az_iot_status_AZ_IOT_STATUS_UNAUTHORIZED),
+    (IotStatus::Forbidden, azsys::az_iot_status_AZ_IOT_STATUS_FORBIDDEN),
+    (IotStatus::NotFound, azsys::az_iot_status_AZ_IOT_STATUS_NOT_FOUND),
+    (IotStatus::NotAllowed, azsys::az_iot_status_AZ_IOT_STATUS_NOT_ALLOWED),
+    (IotStatus::Conflict, azsys::az_iot_status_AZ_IOT_STATUS_NOT_CONFLICT),
+    (IotStatus::PreconditionFailed, azsys::az_iot_status_AZ_IOT_STATUS_PRECONDITION_FAILED),
+    (IotStatus::RequestTooLarge, azsys::az_iot_status_AZ_IOT_STATUS_REQUEST_TOO_LARGE),
+    (IotStatus::UnsupportedType, azsys::az_iot_status_AZ_IOT_STATUS_UNSUPPORTED_TYPE),
+    (IotStatus::Throttled, azsys::az_iot_status_AZ_IOT_STATUS_THROTTLED),
+    (IotStatus::ClientClosed, azsys::az_iot_status_AZ_IOT_STATUS_CLIENT_CLOSED),
+    (IotStatus::ServerError, azsys::az_iot_status_AZ_IOT_STATUS_SERVER_ERROR),
+    (IotStatus::BadGateway, azsys::az_iot_status_AZ_IOT_STATUS_BAD_GATEWAY),
+    (IotStatus::ServiceUnavailable, azsys::az_iot_status_AZ_IOT_STATUS_SERVICE_UNAVAILABLE),
+    (IotStatus::Timeout, azsys::az_iot_status_AZ_IOT_STATUS_TIMEOUT),
+];
+
+impl IotStatus {
+    pub fn from_u16(value: u16) -> IotStatus {
+        for (status, raw) in IOT_STATUSES.iter() {
+            if *raw == value as azsys::az_iot_status {
+                return *status;
+            }
+        }
+
+        IotStatus::Unknown(value)
+    }
+
+    pub fn as_u16(&self) -> u16 {
+        match self {
+            IotStatus::Unknown(value) => *value,
+            _ => {
+                for (status, raw) in IOT_STATUSES.iter() {
+                    if status == self {
+                        return *raw as u16;
+                    }
+                }
+                unreachable!("Status missing from table")
+            }
+        }
+    }
+
+    /// True for any status below 400. az_iot_status_succeeded is inline in the C headers so
+    /// there is no binding for it and the test is repeated here.
+    pub fn is_success(&self) -> bool {
+        (self.as_u16() as azsys::az_iot_status) < azsys::az_iot_status_AZ_IOT_STATUS_BAD_REQUEST
+    }
+
+    /// True when the service expects the request to be repeated later, such as when throttled
+    /// or when it is temporarily unavailable, as decided by az_iot_status_retriable.
+    pub fn is_retriable(&self) -> bool {
+        unsafe { azsys::az_iot_status_retriable(self.as_u16() as azsys::az_iot_status) }
+    }
+}
+
+impl From<u16> for IotStatus {
+    fn from(value: u16) -> IotStatus {
+        IotStatus::from_u16(value)
+    }
+}
+
+impl From<IotStatus> for u16 {
+    fn from(value: IotStatus) -> u16 {
+        value.as_u16()
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum TwinResponseType {
+    Get,
+    DesiredProperties,
+    ReportedProperties,
+    Unknown,
+}
+
+pub struct ClientTwinResponse<'a> {
+    response_type: TwinResponseType,
+    status: u16,
+    request_id: AzSpan<'a>,
+    version: AzSpan<'a>,
+}
+
+impl<'a> ClientTwinResponse<'a> {
+    pub fn new_empty() -> ClientTwinResponse<'a> {
+        ClientTwinResponse {
+            response_type: TwinResponseType::Unknown,
+            status: 0,
+            request_id: AzSpan::empty(),
+            version: AzSpan::empty(),
+        }
+    }
+
+    pub fn get_response_type(&self) -> TwinResponseType {
+        self.response_type
+    }
+
+    pub fn get_status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn get_iot_status(&self) -> IotStatus {
+        IotStatus::from_u16(self.status)
+    }
+
+    pub fn get_request_id(&self) -> Result<&'a str, AzReturnCode> {
+        Ok(self.request_id.as_str()?)
+    }
+
+    pub fn get_version(&self) -> Result<&'a str, AzReturnCode> {
+        Ok(self.version.as_str()?)
     }
 }
 
@@ -787,6 +1560,33 @@ mod tests {
         let _test = std::mem::ManuallyDrop::new(test);
     }
     #[test]
+    fn client_from_temporary_strings() {
+        let client = {
+            let host_name = String::from(HOST_NAME);
+            let device_id = String::from(DEVICE_ID);
+            HubClient::new(&host_name, &device_id, Option::None).unwrap()
+        };
+        assert_eq!(client.get_host_name(), HOST_NAME);
+        assert_eq!(client.get_device_id(), DEVICE_ID);
+        assert_eq!(client.get_client_id().unwrap(), DEVICE_ID);
+        let user_name = format!("{}/{}/?api-version=2020-09-30&DeviceClientType=c%2F1.1.0-beta.2", HOST_NAME, DEVICE_ID);
+        assert_eq!(client.get_user_name().unwrap(), user_name);
+    }
+    #[test]
+    fn client_builder_from_temporary_strings() {
+        let client = {
+            let host_name = String::from(HOST_NAME);
+            let device_id = String::from(DEVICE_ID);
+            HubClientBuilder::new()
+                .host_name(&host_name)
+                .device_id(&device_id)
+                .finalize()
+                .unwrap()
+        };
+        assert_eq!(client.get_host_name(), HOST_NAME);
+        assert_eq!(client.get_client_id().unwrap(), DEVICE_ID);
+    }
+    #[test]
     fn client_get_client_id() {
         let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
         let output = client.get_client_id().unwrap();
@@ -809,6 +1609,59 @@ mod tests {
         assert_eq!(rc, AzReturnCode::AzResultCoreErrorNotEnoughSpace);
     }
     #[test]
+    fn client_get_into_static_buffers() {
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        let mut buffer = [0u8; 256];
+        assert_eq!(client.get_client_id_into(&mut buffer), Ok(DEVICE_ID));
+        assert_eq!(client.get_user_name_into(&mut buffer).map(String::from), client.get_user_name());
+        assert_eq!(
+            client.get_telemetry_publish_topic_into(Option::None, &mut buffer).map(String::from),
+            client.get_telemetry_publish_topic(Option::None)
+        );
+        let mp = MessagePropertiesBuilder::new().add("a", "b").finialize().unwrap();
+        assert_eq!(
+            client.get_telemetry_publish_topic_into(Option::Some(&mp), &mut buffer).map(String::from),
+            Ok(format!("devices/{}/messages/events/a=b", DEVICE_ID))
+        );
+        assert_eq!(
+            client.methods_response_get_publish_topic_into("1", 200u16, &mut buffer).map(String::from),
+            client.methods_response_get_publish_topic("1", 200u16)
+        );
+        assert_eq!(
+            client.twin_document_get_publish_topic_into("2", &mut buffer).map(String::from),
+            client.twin_document_get_publish_topic("2")
+        );
+        assert_eq!(
+            client.twin_patch_get_publish_topic_into("3", &mut buffer).map(String::from),
+            client.twin_patch_get_publish_topic("3")
+        );
+        assert_eq!(
+            client.get_sas_signature_into(100, &mut buffer).map(|s| s.to_vec()),
+            client.get_sas_signature(100)
+        );
+        assert_eq!(
+            client.get_sas_password_into(100, "sig", Option::Some("key"), &mut buffer).map(String::from),
+            client.get_sas_password(100, "sig", Option::Some("key"))
+        );
+    }
+    #[test]
+    fn client_get_into_too_small() {
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        let mut buffer = [0u8; 2];
+        assert_eq!(
+            client.get_client_id_into(&mut buffer),
+            Err(AzReturnCode::AzResultCoreErrorNotEnoughSpace)
+        );
+        assert_eq!(
+            client.get_sas_signature_into(100, &mut buffer),
+            Err(AzReturnCode::AzResultCoreErrorNotEnoughSpace)
+        );
+        assert_eq!(
+            client.get_user_name_into(&mut []),
+            Err(AzReturnCode::AzResultCoreErrorNotEnoughSpace)
+        );
+    }
+    #[test]
     fn client_get_user_name() {
         let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
         let user_name = format!("{}/{}/?api-version=2020-09-30&DeviceClientType=c%2F1.1.0-beta.2", HOST_NAME, DEVICE_ID);
@@ -824,6 +1677,120 @@ mod tests {
         assert_eq!(user_name, out);
     }
     #[test]
+    fn client_options_module_id() {
+        let options = HubClientOptions::default_new().module_id("module1");
+        assert_eq!(options.get_module_id(), Some("module1"));
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::Some(options)).unwrap();
+        assert_eq!(client.get_client_id().unwrap(), format!("{}/module1", DEVICE_ID));
+        let user_name = format!("{}/{}/module1/?api-version=2020-09-30&DeviceClientType=c%2F1.1.0-beta.2", HOST_NAME, DEVICE_ID);
+        assert_eq!(client.get_user_name().unwrap(), user_name);
+    }
+    #[test]
+    fn client_options_model_id() {
+        let options = HubClientOptions::default_new().model_id("dtmi:com:example:Thermostat;1");
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::Some(options)).unwrap();
+        let user_name = format!(
+            "{}/{}/?api-version=2020-09-30&DeviceClientType=c%2F1.1.0-beta.2&model-id=dtmi%3Acom%3Aexample%3AThermostat%3B1",
+            HOST_NAME, DEVICE_ID
+        );
+        assert_eq!(client.get_user_name().unwrap(), user_name);
+    }
+    #[test]
+    fn client_options_user_agent() {
+        let options = HubClientOptions::default_new().user_agent("DeviceClientType=rust%2F0.2.3");
+        let client = HubClientBuilder::new()
+            .host_name(HOST_NAME)
+            .device_id(DEVICE_ID)
+            .client_options(options)
+            .finalize()
+            .unwrap();
+        let user_name = format!("{}/{}/?api-version=2020-09-30&DeviceClientType=rust%2F0.2.3", HOST_NAME, DEVICE_ID);
+        assert_eq!(client.get_user_name().unwrap(), user_name);
+    }
+    #[test]
+    fn client_options_outlive_source_strings() {
+        let client = {
+            let module_id = String::from("module1");
+            let options = HubClientOptions::default_new().module_id(&module_id);
+            HubClient::new(HOST_NAME, DEVICE_ID, Option::Some(options)).unwrap()
+        };
+        assert_eq!(client.get_client_id().unwrap(), format!("{}/module1", DEVICE_ID));
+    }
+    #[test]
+    fn client_module_identity() {
+        let options = HubClientOptions::default_new().module_id("module1");
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::Some(options)).unwrap();
+        assert_eq!(client.get_device_id(), DEVICE_ID);
+        assert_eq!(client.get_module_id(), Some("module1"));
+        assert_eq!(
+            client.get_telemetry_publish_topic(Option::None).unwrap(),
+            format!("devices/{}/modules/module1/messages/events/", DEVICE_ID)
+        );
+        let signature = HOST_NAME.to_string() + "%2Fdevices%2F" + DEVICE_ID + "%2Fmodules%2Fmodule1\n100";
+        assert_eq!(
+            String::from_utf8_lossy(&client.get_sas_signature(100).unwrap()),
+            signature
+        );
+        let password = "SharedAccessSignature sr=".to_string()
+            + HOST_NAME
+            + "%2Fdevices%2F"
+            + DEVICE_ID
+            + "%2Fmodules%2Fmodule1&sig=NotReallyASASToken&se=100";
+        assert_eq!(client.get_sas_password(100, "NotReallyASASToken", Option::None).unwrap(), password);
+    }
+    #[test]
+    fn client_module_input_subscribe_topic() {
+        let options = HubClientOptions::default_new().module_id("module1");
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::Some(options)).unwrap();
+        assert_eq!(
+            client.get_module_input_subscribe_topic().unwrap(),
+            format!("devices/{}/modules/module1/inputs/#", DEVICE_ID)
+        );
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        assert_eq!(
+            client.get_module_input_subscribe_topic(),
+            Err(AzReturnCode::AzResultCoreErrorNotSupported)
+        );
+    }
+    #[test]
+    fn client_module_input_parse_received_topic() {
+        let options = HubClientOptions::default_new().module_id("module1");
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::Some(options)).unwrap();
+        let topic = format!("devices/{}/modules/module1/inputs/input1/FirstName=Harold&LastName=Thomas", DEVICE_ID);
+        match client.get_topic_type(&topic).unwrap() {
+            TopicType::ModuleInput(req) => {
+                assert_eq!(req.get_input_name(), "input1");
+                let mp = req.get_message_properties();
+                assert_eq!(Ok("Harold"), mp.find("FirstName").as_deref());
+                assert_eq!(Ok("Thomas"), mp.find("LastName").as_deref());
+            }
+            _ => panic!("Expected ModuleInput"),
+        }
+        let topic = format!("devices/{}/modules/module2/inputs/input1/", DEVICE_ID);
+        assert!(matches!(
+            client.module_input_parse_received_topic(&topic),
+            Err(AzReturnCode::AzResultIoTErrorTopicNoMatch)
+        ));
+        let topic = format!("devices/{}/modules/module1/inputs/input1", DEVICE_ID);
+        assert!(matches!(
+            client.module_input_parse_received_topic(&topic),
+            Err(AzReturnCode::AzResultIoTErrorTopicNoMatch)
+        ));
+        let topic = format!("devices/{}x/modules/module1/inputs/input1/", DEVICE_ID);
+        assert!(matches!(
+            client.module_input_parse_received_topic(&topic),
+            Err(AzReturnCode::AzResultIoTErrorTopicNoMatch)
+        ));
+
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        let topic = format!("devices/{}/modules/module1/inputs/input1/", DEVICE_ID);
+        assert!(matches!(
+            client.module_input_parse_received_topic(&topic),
+            Err(AzReturnCode::AzResultCoreErrorNotSupported)
+        ));
+        assert!(matches!(client.get_topic_type(&topic), Ok(TopicType::Unknown)));
+    }
+    #[test]
     fn client_get_telemetry_publish_topic() {
         let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
         let topic = "devices/".to_string() + DEVICE_ID + "/messages/events/";
@@ -842,6 +1809,55 @@ mod tests {
         assert_eq!(topic, out);
     }
     #[test]
+    fn client_get_telemetry_publish_topic_large_properties() {
+        let options = HubClientOptions::default_new().module_id("module1");
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::Some(options)).unwrap();
+        let mut builder = MessagePropertiesBuilder::new();
+        for i in 0..200 {
+            builder = builder.add(&format!("property{}", i), &format!("value {}/{}", i, i));
+        }
+        let mp = Option::Some(builder.finialize().unwrap());
+
+        let mut calls = 0;
+        let topic: String = grow_until_fits(client.telemetry_topic_capacity(mp.as_ref()), |result| {
+            calls += 1;
+            client.ll_get_telemetry_publish_topic(&mp, result)
+        })
+        .unwrap();
+        assert!(topic.len() > 5000);
+        assert_eq!(calls, 1);
+
+        // Without an estimate the buffer still only doubles a handful of times
+        calls = 0;
+        let grown: String = grow_until_fits(0, |result| {
+            calls += 1;
+            client.ll_get_telemetry_publish_topic(&mp, result)
+        })
+        .unwrap();
+        assert_eq!(grown, topic);
+        assert!(calls <= 8);
+        assert_eq!(client.get_telemetry_publish_topic(mp).unwrap(), topic);
+    }
+    #[test]
+    fn grow_until_fits_attempts() {
+        let mut attempts = 0;
+        let result: Vec<u8> = grow_until_fits(100, |result: &mut Vec<u8>| {
+            attempts += 1;
+            if result.capacity() < 10_000 {
+                return AzReturnCode::AzResultCoreErrorNotEnoughSpace;
+            }
+            result.extend_from_slice(b"fits");
+            AzReturnCode::AzResultCoreOk
+        })
+        .unwrap();
+        assert_eq!(result, b"fits");
+        // Growing by 2% needed over two hundred attempts to get from 100 to 10,000 bytes
+        assert!(attempts <= 8);
+
+        let result: Result<String, AzReturnCode> = grow_until_fits(100, |_| AzReturnCode::AzResultCoreErrorArg);
+        assert_eq!(result, Err(AzReturnCode::AzResultCoreErrorArg));
+    }
+    #[test]
     fn client_get_sas_signature() {
         let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
         let signature = HOST_NAME.to_string() + "%2Fdevices%2F" + DEVICE_ID + "\n100"; // &DeviceClientType=c%2F1.0.0";
@@ -870,7 +1886,20 @@ mod tests {
             + "&sig="
             + sas
             + "&se=100";
-        assert_eq!(&password, &client.get_sas_password(100, sas).unwrap());
+        assert_eq!(&password, &client.get_sas_password(100, sas, Option::None).unwrap());
+    }
+    #[test]
+    fn client_get_sas_password_key_name() {
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        let sas = "NotReallyASASToken";
+        let password = "SharedAccessSignature sr=".to_string()
+            + HOST_NAME
+            + "%2Fdevices%2F"
+            + DEVICE_ID
+            + "&sig="
+            + sas
+            + "&se=100&skn=iothubowner";
+        assert_eq!(password, client.get_sas_password(100, sas, Option::Some("iothubowner")).unwrap());
     }
     #[test]
     fn client_ll_get_sas_password() {
@@ -884,11 +1913,48 @@ mod tests {
             + sas
             + "&se=100";
         let mut out = String::with_capacity(200);
-        let rc = client.ll_get_sas_password(100, sas, &mut out);
+        let rc = client.ll_get_sas_password(100, sas, Option::None, &mut out);
         assert_eq!(rc, AzReturnCode::AzResultCoreOk);
         assert_eq!(out, password);
     }
     #[test]
+    fn client_ll_get_sas_password_key_name() {
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        let sas = "NotReallyASASToken";
+        let password = "SharedAccessSignature sr=".to_string()
+            + HOST_NAME
+            + "%2Fdevices%2F"
+            + DEVICE_ID
+            + "&sig="
+            + sas
+            + "&se=100&skn=iothubowner";
+        let mut out = String::with_capacity(200);
+        let rc = client.ll_get_sas_password(100, sas, Option::Some("iothubowner"), &mut out);
+        assert_eq!(rc, AzReturnCode::AzResultCoreOk);
+        assert_eq!(out, password);
+    }
+    #[cfg(feature = "sas-token")]
+    #[test]
+    fn client_generate_sas_password() {
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        let key = "MDEyMzQ1Njc4OTAxMjM0NTY3ODkwMTIzNDU2Nzg5MDE=";
+        let password = "SharedAccessSignature sr=".to_string()
+            + HOST_NAME
+            + "%2Fdevices%2F"
+            + DEVICE_ID
+            + "&sig=5FzXTZWaBl9%2FMguB7JvvTmVZ6PlyWR%2B44DtV13iW%2Fso%3D&se=100";
+        assert_eq!(client.generate_sas_password(key, 100, Option::None).unwrap(), password);
+    }
+    #[cfg(feature = "sas-token")]
+    #[test]
+    fn client_generate_sas_password_bad_key() {
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        assert_eq!(
+            client.generate_sas_password("not base64!", 100, Option::None),
+            Err(AzReturnCode::AzResultCoreErrorArg)
+        );
+    }
+    #[test]
     fn client_get_c2d_subscribe_topic() {
         assert_eq!(
             HubClient::get_c2d_subscribe_topic(),
@@ -903,21 +1969,219 @@ mod tests {
         );
     }
     #[test]
+    fn client_twin_document_get_publish_topic() {
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        assert_eq!(
+            client.twin_document_get_publish_topic("1").unwrap(),
+            "$iothub/twin/GET/?$rid=1"
+        );
+    }
+    #[test]
+    fn client_ll_twin_document_get_publish_topic() {
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        let mut out = String::with_capacity(200);
+        let rc = client.ll_twin_document_get_publish_topic("1", &mut out);
+        assert_eq!(rc, AzReturnCode::AzResultCoreOk);
+        assert_eq!(out, "$iothub/twin/GET/?$rid=1");
+    }
+    #[test]
+    fn client_twin_patch_get_publish_topic() {
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        assert_eq!(
+            client.twin_patch_get_publish_topic("2").unwrap(),
+            "$iothub/twin/PATCH/properties/reported/?$rid=2"
+        );
+    }
+    #[test]
+    fn client_ll_twin_patch_get_publish_topic() {
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        let mut out = String::with_capacity(200);
+        let rc = client.ll_twin_patch_get_publish_topic("2", &mut out);
+        assert_eq!(rc, AzReturnCode::AzResultCoreOk);
+        assert_eq!(out, "$iothub/twin/PATCH/properties/reported/?$rid=2");
+    }
+    #[test]
+    fn client_twin_parse_received_topic() {
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        let response = client
+            .twin_parse_received_topic("$iothub/twin/res/200/?$rid=1")
+            .unwrap();
+        assert_eq!(response.get_response_type(), TwinResponseType::Get);
+        assert_eq!(response.get_status(), 200);
+        assert_eq!(response.get_request_id(), Ok("1"));
+        assert_eq!(response.get_version(), Ok(""));
+
+        let response = client
+            .twin_parse_received_topic("$iothub/twin/res/204/?$rid=2&$version=5")
+            .unwrap();
+        assert_eq!(response.get_response_type(), TwinResponseType::ReportedProperties);
+        assert_eq!(response.get_status(), 204);
+        assert_eq!(response.get_request_id(), Ok("2"));
+        assert_eq!(response.get_version(), Ok("5"));
+
+        let response = client
+            .twin_parse_received_topic("$iothub/twin/PATCH/properties/desired/?$version=7")
+            .unwrap();
+        assert_eq!(response.get_response_type(), TwinResponseType::DesiredProperties);
+        assert_eq!(response.get_request_id(), Ok(""));
+        assert_eq!(response.get_version(), Ok("7"));
+    }
+    #[test]
+    fn client_twin_parse_received_topic_no_match() {
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        assert!(matches!(
+            client.twin_parse_received_topic("$iothub/methods/POST/reboot/?$rid=1"),
+            Err(AzReturnCode::AzResultIoTErrorTopicNoMatch)
+        ));
+    }
+    #[test]
+    fn client_get_topic_type_twin() {
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        match client.get_topic_type("$iothub/twin/res/200/?$rid=1").unwrap() {
+            TopicType::TwinGetResponse(r) => {
+                assert_eq!(r.get_status(), 200);
+                assert_eq!(r.get_request_id(), Ok("1"));
+            }
+            _ => panic!("Expected TwinGetResponse"),
+        }
+        match client.get_topic_type("$iothub/twin/res/204/?$rid=2&$version=5").unwrap() {
+            TopicType::TwinReportedResponse(r) => {
+                assert_eq!(r.get_status(), 204);
+                assert_eq!(r.get_iot_status(), IotStatus::NoContent);
+                assert_eq!(r.get_request_id(), Ok("2"));
+                assert_eq!(r.get_version(), Ok("5"));
+            }
+            _ => panic!("Expected TwinReportedResponse"),
+        }
+        match client.get_topic_type("$iothub/twin/PATCH/properties/desired/?$version=7").unwrap() {
+            TopicType::TwinDesiredPatch(r) => assert_eq!(r.get_version(), Ok("7")),
+            _ => panic!("Expected TwinDesiredPatch"),
+        }
+    }
+    #[test]
+    fn topic_type_unknown_twin_response() {
+        let topic = AzSpan::from("$iothub/twin/res/429/?$rid=3");
+        let response = ClientTwinResponse {
+            response_type: TwinResponseType::Unknown,
+            status: 429,
+            request_id: topic.slice_to_end(27).unwrap(),
+            version: AzSpan::empty(),
+        };
+        match TopicType::from_twin_response(response) {
+            TopicType::TwinResponse(r) => {
+                assert_eq!(r.get_iot_status(), IotStatus::Throttled);
+                assert_eq!(r.get_request_id(), Ok("3"));
+            }
+            _ => panic!("Expected TwinResponse"),
+        }
+    }
+    #[test]
+    fn iot_status_round_trip() {
+        for (status, value) in IOT_STATUSES.iter() {
+            assert_eq!(IotStatus::from_u16(*value as u16), *status);
+            assert_eq!(status.as_u16() as azsys::az_iot_status, *value);
+        }
+        assert_eq!(IotStatus::from(418), IotStatus::Unknown(418));
+        assert_eq!(u16::from(IotStatus::Unknown(418)), 418);
+        assert_eq!(u16::from(IotStatus::Conflict), 409);
+    }
+    #[test]
+    fn iot_status_helpers() {
+        assert!(IotStatus::Ok.is_success());
+        assert!(IotStatus::NoContent.is_success());
+        assert!(!IotStatus::BadRequest.is_success());
+        assert!(!IotStatus::Unknown(600).is_success());
+        assert!(IotStatus::Throttled.is_retriable());
+        assert!(IotStatus::ServerError.is_retriable());
+        assert!(!IotStatus::Ok.is_retriable());
+        assert!(!IotStatus::Unauthorized.is_retriable());
+    }
+    #[test]
+    fn client_methods_response_iot_status() {
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        assert_eq!(
+            client.methods_response_get_publish_topic("1", IotStatus::NotFound),
+            Ok("$iothub/methods/res/404/?$rid=1".to_string())
+        );
+        assert_eq!(
+            client.methods_response_get_publish_topic("1", IotStatus::Ok),
+            client.methods_response_get_publish_topic("1", 200u16)
+        );
+        let mut result = String::with_capacity(64);
+        assert_eq!(
+            client.ll_methods_response_get_publish_topic("1", IotStatus::Unknown(299), &mut result),
+            AzReturnCode::AzResultCoreOk
+        );
+        assert_eq!(result, "$iothub/methods/res/299/?$rid=1");
+    }
+    #[test]
+    fn client_get_topic_type_unknown() {
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        assert!(matches!(
+            client.get_topic_type("some/other/topic").unwrap(),
+            TopicType::Unknown
+        ));
+    }
+    #[test]
+    fn client_get_topic_type_method() {
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        match client.get_topic_type("$iothub/methods/POST/reboot/?$rid=10").unwrap() {
+            TopicType::Method(r) => {
+                assert_eq!(r.get_name(), Ok("reboot"));
+                assert_eq!(r.get_name_bytes(), b"reboot");
+                assert_eq!(r.get_request_id(), Ok("10"));
+                assert_eq!(r.get_request_id_bytes(), b"10");
+            }
+            _ => panic!("Expected Method"),
+        }
+    }
+    #[test]
+    fn client_get_topic_type_from_bytes() {
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        assert!(matches!(
+            client.get_topic_type_from_bytes(b"$iothub/methods/POST/reboot/?$rid=10").unwrap(),
+            TopicType::Method(_)
+        ));
+        assert_eq!(
+            client.get_topic_type_from_bytes(b"$iothub/methods/POST/re\xffboot/?$rid=10").err(),
+            Option::Some(AzReturnCode::AzResultCoreErrorUnexpectedChar)
+        );
+    }
+    #[test]
+    fn test_message_properties_invalid_utf8() {
+        let buf: Vec<u8> = b"name=va\xfflue".to_vec();
+        let len = buf.len() as i32;
+        let mp = MessageProperties::new(buf, len).unwrap();
+        assert_eq!(mp.find("name"), Err(AzReturnCode::AzResultCoreErrorUnexpectedChar));
+        assert_eq!(mp.into_array().err(), Option::Some(AzReturnCode::AzResultCoreErrorUnexpectedChar));
+        assert_eq!(mp.len(), 0);
+        assert!(mp.is_empty());
+        assert!(!mp.contains_key("name"));
+        let buf: Vec<u8> = b"good=1&name=va\xfflue".to_vec();
+        let len = buf.len() as i32;
+        let mp = MessageProperties::new(buf, len).unwrap();
+        assert_eq!(mp.iter().count(), 2);
+        assert_eq!(mp.len(), 1);
+        assert!(!mp.is_empty());
+        assert!(mp.contains_key("good"));
+        assert!(!mp.contains_key("name"));
+    }
+    #[test]
     fn test_message_properties() {
         let buf: Vec<u8> = Vec::with_capacity(200);
         let mut mp = MessageProperties::new(buf, 0).unwrap();
         mp.append("LastName", "Thomas").unwrap();
         mp.append("MiddleName", "Richard").unwrap();
         mp.append("FirstName", "Harold").unwrap();
-        assert_eq!(Ok("Richard"), mp.find("MiddleName"));
-        assert_eq!(Ok("Harold"), mp.find("FirstName"));
-        assert_eq!(Ok("Thomas"), mp.find("LastName"));
+        assert_eq!(Ok("Richard"), mp.find("MiddleName").as_deref());
+        assert_eq!(Ok("Harold"), mp.find("FirstName").as_deref());
+        assert_eq!(Ok("Thomas"), mp.find("LastName").as_deref());
         // Pending bug fix in azure-sdk-for-c
         let out = mp.into_array().unwrap();
         assert_eq!(out.len(), 3);
         
         for val in out {
-            match val.0 {
+            match val.0.as_ref() {
                 "LastName" => assert_eq!(val.1, "Thomas"),
                 "MiddleName" => assert_eq!(val.1, "Richard"),
                 "FirstName" => assert_eq!(val.1, "Harold"),
@@ -926,21 +2190,154 @@ mod tests {
         }
     }
     #[test]
+    fn test_message_properties_iter() {
+        let mp = MessagePropertiesBuilder::new()
+            .add("LastName", "Thomas")
+            .add("FirstName", "Harold")
+            .finialize()
+            .unwrap();
+        let expected = vec![
+            (Cow::from("LastName"), Cow::from("Thomas")),
+            (Cow::from("FirstName"), Cow::from("Harold")),
+        ];
+        assert_eq!(mp.iter().collect::<Result<Vec<_>, _>>().unwrap(), expected);
+        assert_eq!(mp.iter().collect::<Result<Vec<_>, _>>().unwrap(), expected);
+        assert_eq!(mp.into_array().unwrap(), expected);
+        assert_eq!(mp.into_array().unwrap(), expected);
+        let mut count = 0;
+        for prop in &mp {
+            assert!(prop.is_ok());
+            count += 1;
+        }
+        assert_eq!(count, 2);
+        assert_eq!(mp.len(), 2);
+        assert!(!mp.is_empty());
+    }
+    #[test]
+    fn test_message_properties_lookup() {
+        let mp = MessagePropertiesBuilder::new()
+            .add("LastName", "Thomas")
+            .add("FirstName", "Harold")
+            .finialize()
+            .unwrap();
+        assert!(mp.contains_key("FirstName"));
+        assert!(!mp.contains_key("MiddleName"));
+        assert!(!mp.contains_key(""));
+        assert_eq!(mp.get("LastName").unwrap().as_deref(), Option::Some("Thomas"));
+        assert_eq!(mp.get("MiddleName"), Ok(Option::None));
+        assert_eq!(mp.find("MiddleName").err(), Option::Some(AzReturnCode::AzResultCoreErrorItemNotFound));
+    }
+    #[test]
+    fn test_message_properties_system_properties() {
+        let mp = MessagePropertiesBuilder::new()
+            .content_type("application/json")
+            .content_encoding("utf-8")
+            .message_id("m1")
+            .correlation_id("c1")
+            .user_id("u1")
+            .component_name("thermostat")
+            .creation_time("2021-03-01T12:00:00.000Z")
+            .add("custom", "value")
+            .finialize()
+            .unwrap();
+        assert_eq!(mp.get_content_type().unwrap().as_deref(), Option::Some("application/json"));
+        assert_eq!(mp.get_content_encoding().unwrap().as_deref(), Option::Some("utf-8"));
+        assert_eq!(mp.get_message_id().unwrap().as_deref(), Option::Some("m1"));
+        assert_eq!(mp.get_correlation_id().unwrap().as_deref(), Option::Some("c1"));
+        assert_eq!(mp.get_user_id().unwrap().as_deref(), Option::Some("u1"));
+        assert_eq!(mp.get_component_name().unwrap().as_deref(), Option::Some("thermostat"));
+        assert_eq!(mp.get_creation_time().unwrap().as_deref(), Option::Some("2021-03-01T12:00:00.000Z"));
+        assert!(mp.contains_key("$.ct"));
+        for (name, constant) in [
+            (MESSAGE_ID_PROPERTY, &azsys::AZ_IOT_MESSAGE_PROPERTIES_MESSAGE_ID[..]),
+            (CORRELATION_ID_PROPERTY, &azsys::AZ_IOT_MESSAGE_PROPERTIES_CORRELATION_ID[..]),
+            (CONTENT_TYPE_PROPERTY, &azsys::AZ_IOT_MESSAGE_PROPERTIES_CONTENT_TYPE[..]),
+            (CONTENT_ENCODING_PROPERTY, &azsys::AZ_IOT_MESSAGE_PROPERTIES_CONTENT_ENCODING[..]),
+            (USER_ID_PROPERTY, &azsys::AZ_IOT_MESSAGE_PROPERTIES_USER_ID[..]),
+            (CREATE_TIME_PROPERTY, &azsys::AZ_IOT_MESSAGE_PROPERTIES_CREATE_TIME[..]),
+            (COMPONENT_NAME_PROPERTY, &azsys::AZ_IOT_MESSAGE_PROPERTIES_COMPONENT_NAME[..]),
+        ] {
+            assert_eq!(name.as_bytes(), constant.strip_suffix(&[0]).unwrap());
+        }
+
+        let mp = MessagePropertiesBuilder::new().add("custom", "value").finialize().unwrap();
+        assert_eq!(mp.get_message_id(), Ok(Option::None));
+    }
+    #[test]
+    fn test_message_properties_encoding_round_trip() {
+        let mp = MessagePropertiesBuilder::new()
+            .add("my key", "a&b=c/d é")
+            .add("plain", "value")
+            .finialize()
+            .unwrap();
+        assert_eq!(mp.find("my key").as_deref(), Ok("a&b=c/d é"));
+        assert_eq!(mp.find("plain").as_deref(), Ok("value"));
+        assert!(matches!(mp.find("plain"), Ok(Cow::Borrowed(_))));
+        let out = mp.into_array().unwrap();
+        assert_eq!(out[0], (Cow::from("my key"), Cow::from("a&b=c/d é")));
+        assert_eq!(out[1], (Cow::from("plain"), Cow::from("value")));
+
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        let topic = client.get_telemetry_publish_topic(Option::Some(mp)).unwrap();
+        assert_eq!(
+            topic,
+            format!("devices/{}/messages/events/my%20key=a%26b%3Dc%2Fd%20%C3%A9&plain=value", DEVICE_ID)
+        );
+    }
+    #[test]
+    fn test_message_properties_append_encoding() {
+        let mut mp = MessageProperties::new(Vec::with_capacity(100), 0).unwrap();
+        mp.append("$.mid", "1 2").unwrap();
+        assert_eq!(mp.get_message_id().unwrap().as_deref(), Option::Some("1 2"));
+        assert_eq!(mp.append("", "value").err(), Option::Some(AzReturnCode::AzResultCoreErrorArg));
+        assert_eq!(mp.append("name", "").err(), Option::Some(AzReturnCode::AzResultCoreErrorArg));
+    }
+    #[test]
+    fn test_c2d_properties_decoded() {
+        let client = HubClient::new(HOST_NAME, DEVICE_ID, Option::None).unwrap();
+        let topic = format!("devices/{}/messages/devicebound/%24.mid=id%201&name=a%2Fb", DEVICE_ID);
+        match client.get_topic_type(&topic).unwrap() {
+            TopicType::C2D(req) => {
+                let mp = req.get_message_properties();
+                assert_eq!(mp.get_message_id().unwrap().as_deref(), Option::Some("id 1"));
+                assert_eq!(mp.find("name").as_deref(), Ok("a/b"));
+            }
+            _ => panic!("Expected C2D"),
+        }
+    }
+    #[test]
+    fn test_url_decode_invalid() {
+        assert_eq!(url_decode(b"abc%4").err(), Option::Some(AzReturnCode::AzResultCoreErrorUnexpectedEnd));
+        assert_eq!(url_decode(b"abc%zz").err(), Option::Some(AzReturnCode::AzResultCoreErrorUnexpectedChar));
+        assert_eq!(url_decode(b"abc%FF").err(), Option::Some(AzReturnCode::AzResultCoreErrorUnexpectedChar));
+        assert_eq!(url_decode(b"%+5").err(), Option::Some(AzReturnCode::AzResultCoreErrorUnexpectedChar));
+        assert_eq!(url_decode(b"%-5").err(), Option::Some(AzReturnCode::AzResultCoreErrorUnexpectedChar));
+        assert_eq!(url_encode("-_.~09azAZ"), "-_.~09azAZ");
+    }
+    #[test]
+    fn test_message_properties_empty() {
+        let mp = MessageProperties::new_empty(Vec::new());
+        assert!(mp.is_empty());
+        assert_eq!(mp.len(), 0);
+        assert!(mp.iter().next().is_none());
+        assert_eq!(mp.get("LastName"), Ok(Option::None));
+    }
+    #[test]
     fn test_message_properties_builder() {
-        let mut mp = MessagePropertiesBuilder::new()
+        let mp = MessagePropertiesBuilder::new()
             .add("LastName", "Thomas")
             .add("MiddleName", "Richard")
             .add("FirstName", "Harold")
             .finialize().unwrap();
-        assert_eq!(Ok("Richard"), mp.find("MiddleName"));
-        assert_eq!(Ok("Harold"), mp.find("FirstName"));
-        assert_eq!(Ok("Thomas"), mp.find("LastName"));
+        assert_eq!(Ok("Richard"), mp.find("MiddleName").as_deref());
+        assert_eq!(Ok("Harold"), mp.find("FirstName").as_deref());
+        assert_eq!(Ok("Thomas"), mp.find("LastName").as_deref());
         // Pending bug fix in azure-sdk-for-c 
         let out = mp.into_array().unwrap();
         assert_eq!(out.len(), 3);
         
         for val in out {
-            match val.0 {
+            match val.0.as_ref() {
                 "LastName" => assert_eq!(val.1, "Thomas"),
                 "MiddleName" => assert_eq!(val.1, "Richard"),
                 "FirstName" => assert_eq!(val.1, "Harold"),
@@ -956,9 +2353,9 @@ mod tests {
             .add("FirstName", "Harold")
             .finialize().unwrap();
         let req = ClientC2DRequest::from_message_properties(mp);
-        let mut mp2 = req.get_message_properties();
-        assert_eq!(Ok("Richard"), mp2.find("MiddleName"));
-        assert_eq!(Ok("Harold"), mp2.find("FirstName"));
-        assert_eq!(Ok("Thomas"), mp2.find("LastName"));
+        let mp2 = req.get_message_properties();
+        assert_eq!(Ok("Richard"), mp2.find("MiddleName").as_deref());
+        assert_eq!(Ok("Harold"), mp2.find("FirstName").as_deref());
+        assert_eq!(Ok("Thomas"), mp2.find("LastName").as_deref());
     }
 }
@@ -0,0 +1,455 @@
+pub use crate::az_iot::{HubClient, HubClientOptions};
+pub use crate::az_return_codes::AzReturnCode;
+use alloc::string::{String, ToString};
+use core::fmt;
+use core::str::FromStr;
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum ConnectionStringError {
+    Empty,
+    MalformedSegment(String),
+    UnknownKey(String),
+    DuplicateKey(String),
+    EmptyValue(String),
+    InvalidValue(String),
+    MissingHostName,
+    MissingDeviceId,
+    MissingCredential,
+    ConflictingCredentials,
+}
+
+impl fmt::Display for ConnectionStringError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConnectionStringError::Empty => write!(f, "Connection string is empty"),
+            ConnectionStringError::MalformedSegment(s) => {
+                write!(f, "Connection string segment '{}' is not in key=value form", s)
+            }
+            ConnectionStringError::UnknownKey(k) => write!(f, "Connection string key '{}' is not recognized", k),
+            ConnectionStringError::DuplicateKey(k) => {
+                write!(f, "Connection string key '{}' is specified more than once", k)
+            }
+            ConnectionStringError::EmptyValue(k) => write!(f, "Connection string key '{}' has no value", k),
+            ConnectionStringError::InvalidValue(k) => {
+                write!(f, "Connection string key '{}' has an invalid value", k)
+            }
+            ConnectionStringError::MissingHostName => write!(f, "Connection string is missing HostName"),
+            ConnectionStringError::MissingDeviceId => write!(f, "Connection string is missing DeviceId"),
+            ConnectionStringError::MissingCredential => {
+                write!(f, "Connection string requires either SharedAccessKey or x509=true")
+            }
+            ConnectionStringError::ConflictingCredentials => {
+                write!(f, "Connection string cannot specify both SharedAccessKey and x509=true")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ConnectionStringError {}
+
+#[derive(PartialEq, Clone)]
+pub enum HubCredential {
+    SharedAccessKey {
+        key: String,
+        key_name: Option<String>,
+    },
+    X509,
+}
+
+#[derive(PartialEq, Clone)]
+pub struct ConnectionString {
+    host_name: String,
+    device_id: String,
+    module_id: Option<String>,
+    shared_access_key: Option<String>,
+    shared_access_key_name: Option<String>,
+    gateway_host_name: Option<String>,
+    x509: bool,
+}
+
+// Stands in for the shared access key in Debug output so that logging a credential does not leak it
+struct Redacted;
+
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<redacted>")
+    }
+}
+
+impl fmt::Debug for HubCredential {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HubCredential::SharedAccessKey { key_name, .. } => f
+                .debug_struct("SharedAccessKey")
+                .field("key", &Redacted)
+                .field("key_name", key_name)
+                .finish(),
+            HubCredential::X509 => write!(f, "X509"),
+        }
+    }
+}
+
+impl fmt::Debug for ConnectionString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConnectionString")
+            .field("host_name", &self.host_name)
+            .field("device_id", &self.device_id)
+            .field("module_id", &self.module_id)
+            .field("shared_access_key", &self.shared_access_key.as_ref().map(|_| Redacted))
+            .field("shared_access_key_name", &self.shared_access_key_name)
+            .field("gateway_host_name", &self.gateway_host_name)
+            .field("x509", &self.x509)
+            .finish()
+    }
+}
+
+impl ConnectionString {
+    pub fn parse(connection_string: &str) -> Result<ConnectionString, ConnectionStringError> {
+        let mut host_name: Option<String> = Option::None;
+        let mut device_id: Option<String> = Option::None;
+        let mut module_id: Option<String> = Option::None;
+        let mut shared_access_key: Option<String> = Option::None;
+        let mut shared_access_key_name: Option<String> = Option::None;
+        let mut gateway_host_name: Option<String> = Option::None;
+        let mut x509: Option<String> = Option::None;
+
+        if connection_string.trim().is_empty() {
+            return Err(ConnectionStringError::Empty);
+        }
+
+        for segment in connection_string.split(';') {
+            // Tolerate a trailing separator
+            if segment.trim().is_empty() {
+                continue;
+            }
+
+            // Only split on the first '=' since base64 keys end with padding
+            let (key, value) = match segment.find('=') {
+                Some(i) => (segment[..i].trim(), segment[i + 1..].trim()),
+                None => return Err(ConnectionStringError::MalformedSegment(segment.to_string())),
+            };
+
+            if key.is_empty() {
+                return Err(ConnectionStringError::MalformedSegment(segment.to_string()));
+            }
+
+            let field = match key {
+                "HostName" => &mut host_name,
+                "DeviceId" => &mut device_id,
+                "ModuleId" => &mut module_id,
+                "SharedAccessKey" => &mut shared_access_key,
+                "SharedAccessKeyName" => &mut shared_access_key_name,
+                "GatewayHostName" => &mut gateway_host_name,
+                "x509" => &mut x509,
+                _ => return Err(ConnectionStringError::UnknownKey(key.to_string())),
+            };
+
+            if field.is_some() {
+                return Err(ConnectionStringError::DuplicateKey(key.to_string()));
+            }
+
+            if value.is_empty() {
+                return Err(ConnectionStringError::EmptyValue(key.to_string()));
+            }
+
+            *field = Option::Some(value.to_string());
+        }
+
+        let host_name = host_name.ok_or(ConnectionStringError::MissingHostName)?;
+        let device_id = device_id.ok_or(ConnectionStringError::MissingDeviceId)?;
+
+        if !is_valid_host_name(&host_name) {
+            return Err(ConnectionStringError::InvalidValue("HostName".to_string()));
+        }
+
+        if let Some(g) = &gateway_host_name {
+            if !is_valid_host_name(g) {
+                return Err(ConnectionStringError::InvalidValue("GatewayHostName".to_string()));
+            }
+        }
+
+        let x509 = match x509 {
+            Some(v) => {
+                if v.eq_ignore_ascii_case("true") {
+                    true
+                } else if v.eq_ignore_ascii_case("false") {
+                    false
+                } else {
+                    return Err(ConnectionStringError::InvalidValue("x509".to_string()));
+                }
+            }
+            None => false,
+        };
+
+        if let Some(k) = &shared_access_key {
+            if !is_valid_base64(k) {
+                return Err(ConnectionStringError::InvalidValue("SharedAccessKey".to_string()));
+            }
+        }
+
+        match (&shared_access_key, x509) {
+            (Some(_), true) => return Err(ConnectionStringError::ConflictingCredentials),
+            (None, false) => return Err(ConnectionStringError::MissingCredential),
+            _ => (),
+        }
+
+        if shared_access_key_name.is_some() && shared_access_key.is_none() {
+            return Err(ConnectionStringError::MissingCredential);
+        }
+
+        Ok(ConnectionString {
+            host_name,
+            device_id,
+            module_id,
+            shared_access_key,
+            shared_access_key_name,
+            gateway_host_name,
+            x509,
+        })
+    }
+
+    pub fn get_host_name(&self) -> &str {
+        &self.host_name
+    }
+
+    pub fn get_device_id(&self) -> &str {
+        &self.device_id
+    }
+
+    pub fn get_module_id(&self) -> Option<&str> {
+        self.module_id.as_deref()
+    }
+
+    pub fn get_shared_access_key(&self) -> Option<&str> {
+        self.shared_access_key.as_deref()
+    }
+
+    pub fn get_shared_access_key_name(&self) -> Option<&str> {
+        self.shared_access_key_name.as_deref()
+    }
+
+    /// When present the MQTT connection should be made to this host rather than the hub.
+    pub fn get_gateway_host_name(&self) -> Option<&str> {
+        self.gateway_host_name.as_deref()
+    }
+
+    pub fn is_x509(&self) -> bool {
+        self.x509
+    }
+
+    pub fn get_credential(&self) -> HubCredential {
+        match &self.shared_access_key {
+            Some(key) => HubCredential::SharedAccessKey {
+                key: key.clone(),
+                key_name: self.shared_access_key_name.clone(),
+            },
+            None => HubCredential::X509,
+        }
+    }
+
+    /// Creates a client for this identity, adding the module id to any supplied options. Options
+    /// that name a different module fail with AzResultCoreErrorArg.
+    pub fn create_hub_client(
+        &self,
+        options: Option<HubClientOptions>,
+    ) -> Result<(HubClient, HubCredential), AzReturnCode> {
+        if let Some(o) = &options {
+            if o.get_module_id().is_some() && o.get_module_id() != self.get_module_id() {
+                return Err(AzReturnCode::AzResultCoreErrorArg);
+            }
+        }
+
+        let options = match &self.module_id {
+            Some(m) => Option::Some(options.unwrap_or_else(HubClientOptions::default_new).module_id(m)),
+            None => options,
+        };
+        let client = HubClient::new(&self.host_name, &self.device_id, options)?;
+
+        Ok((client, self.get_credential()))
+    }
+}
+
+impl FromStr for ConnectionString {
+    type Err = ConnectionStringError;
+
+    fn from_str(s: &str) -> Result<ConnectionString, ConnectionStringError> {
+        ConnectionString::parse(s)
+    }
+}
+
+fn is_valid_host_name(host_name: &str) -> bool {
+    host_name
+        .split('.')
+        .all(|label| !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+}
+
+fn is_valid_base64(value: &str) -> bool {
+    let data = value.trim_end_matches('=');
+    let padding = value.len() - data.len();
+
+    // Padded base64 always encodes to a multiple of four characters
+    value.len() / 4 * 4 == value.len()
+        && padding <= 2
+        && data.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    static KEY: &str = "MDEyMzQ1Njc4OTAxMjM0NTY3ODkwMTIzNDU2Nzg5MDE=";
+    #[test]
+    fn connection_string_device() {
+        let cs = ConnectionString::parse(&format!(
+            "HostName=testhost.azure-devices.net;DeviceId=test1;SharedAccessKey={}",
+            KEY
+        ))
+        .unwrap();
+        assert_eq!(cs.get_host_name(), "testhost.azure-devices.net");
+        assert_eq!(cs.get_device_id(), "test1");
+        assert_eq!(cs.get_module_id(), None);
+        assert_eq!(cs.get_shared_access_key(), Some(KEY));
+        assert_eq!(cs.get_gateway_host_name(), None);
+        assert!(!cs.is_x509());
+        assert_eq!(
+            cs.get_credential(),
+            HubCredential::SharedAccessKey {
+                key: KEY.to_string(),
+                key_name: None
+            }
+        );
+    }
+    #[test]
+    fn connection_string_module_gateway() {
+        let cs: ConnectionString = format!(
+            "HostName=testhost.azure-devices.net;DeviceId=test1;ModuleId=module1;SharedAccessKey={};GatewayHostName=edge.local;",
+            KEY
+        )
+        .parse()
+        .unwrap();
+        assert_eq!(cs.get_module_id(), Some("module1"));
+        assert_eq!(cs.get_gateway_host_name(), Some("edge.local"));
+    }
+    #[test]
+    fn connection_string_x509() {
+        let cs = ConnectionString::parse("HostName=testhost.azure-devices.net;DeviceId=test1;x509=true").unwrap();
+        assert!(cs.is_x509());
+        assert_eq!(cs.get_credential(), HubCredential::X509);
+        let cs = ConnectionString::parse(&format!(
+            "HostName=testhost.azure-devices.net;DeviceId=test1;SharedAccessKey={};x509=False",
+            KEY
+        ))
+        .unwrap();
+        assert!(!cs.is_x509());
+        assert_eq!(
+            ConnectionString::parse("HostName=testhost.azure-devices.net;DeviceId=test1;x509=false"),
+            Err(ConnectionStringError::MissingCredential)
+        );
+    }
+    #[test]
+    fn connection_string_key_name() {
+        let cs = ConnectionString::parse(&format!(
+            "HostName=testhost.azure-devices.net;DeviceId=test1;SharedAccessKeyName=iothubowner;SharedAccessKey={}",
+            KEY
+        ))
+        .unwrap();
+        assert_eq!(cs.get_shared_access_key_name(), Some("iothubowner"));
+    }
+    #[test]
+    fn connection_string_errors() {
+        assert_eq!(ConnectionString::parse(""), Err(ConnectionStringError::Empty));
+        assert_eq!(
+            ConnectionString::parse("HostName=testhost.azure-devices.net;DeviceId"),
+            Err(ConnectionStringError::MalformedSegment("DeviceId".to_string()))
+        );
+        assert_eq!(
+            ConnectionString::parse("HostName=testhost.azure-devices.net;Device=test1"),
+            Err(ConnectionStringError::UnknownKey("Device".to_string()))
+        );
+        assert_eq!(
+            ConnectionString::parse("HostName=a.net;HostName=b.net;DeviceId=test1;x509=true"),
+            Err(ConnectionStringError::DuplicateKey("HostName".to_string()))
+        );
+        assert_eq!(
+            ConnectionString::parse("HostName=testhost.azure-devices.net;DeviceId=;x509=true"),
+            Err(ConnectionStringError::EmptyValue("DeviceId".to_string()))
+        );
+        assert_eq!(
+            ConnectionString::parse("DeviceId=test1;x509=true"),
+            Err(ConnectionStringError::MissingHostName)
+        );
+        assert_eq!(
+            ConnectionString::parse("HostName=testhost.azure-devices.net;x509=true"),
+            Err(ConnectionStringError::MissingDeviceId)
+        );
+        assert_eq!(
+            ConnectionString::parse("HostName=testhost.azure-devices.net;DeviceId=test1"),
+            Err(ConnectionStringError::MissingCredential)
+        );
+        assert_eq!(
+            ConnectionString::parse(&format!(
+                "HostName=testhost.azure-devices.net;DeviceId=test1;x509=true;SharedAccessKey={}",
+                KEY
+            )),
+            Err(ConnectionStringError::ConflictingCredentials)
+        );
+        assert_eq!(
+            ConnectionString::parse("HostName=testhost.azure-devices.net;DeviceId=test1;x509=yes"),
+            Err(ConnectionStringError::InvalidValue("x509".to_string()))
+        );
+        assert_eq!(
+            ConnectionString::parse("HostName=testhost.azure-devices.net;DeviceId=test1;SharedAccessKey=not base64"),
+            Err(ConnectionStringError::InvalidValue("SharedAccessKey".to_string()))
+        );
+        assert_eq!(
+            ConnectionString::parse("HostName=bad host;DeviceId=test1;x509=true"),
+            Err(ConnectionStringError::InvalidValue("HostName".to_string()))
+        );
+    }
+    #[test]
+    fn connection_string_create_hub_client() {
+        let cs = ConnectionString::parse(&format!(
+            "HostName=testhost.azure-devices.net;DeviceId=test1;ModuleId=module1;SharedAccessKey={}",
+            KEY
+        ))
+        .unwrap();
+        let (client, credential) = cs.create_hub_client(Option::None).unwrap();
+        assert_eq!(client.get_client_id().unwrap(), "test1/module1");
+        assert_eq!(
+            credential,
+            HubCredential::SharedAccessKey {
+                key: KEY.to_string(),
+                key_name: None
+            }
+        );
+
+        let options = HubClientOptions::default_new().module_id("module1").model_id("dtmi:test;1");
+        let (client, _) = cs.create_hub_client(Option::Some(options)).unwrap();
+        assert_eq!(client.get_client_id().unwrap(), "test1/module1");
+        let options = HubClientOptions::default_new().module_id("module2");
+        assert!(matches!(
+            cs.create_hub_client(Option::Some(options)),
+            Err(AzReturnCode::AzResultCoreErrorArg)
+        ));
+
+        let cs = ConnectionString::parse(&format!(
+            "HostName=testhost.azure-devices.net;DeviceId=test1;SharedAccessKey={}",
+            KEY
+        ))
+        .unwrap();
+        let options = HubClientOptions::default_new().module_id("module1");
+        assert!(matches!(
+            cs.create_hub_client(Option::Some(options)),
+            Err(AzReturnCode::AzResultCoreErrorArg)
+        ));
+    }    #[test]
+    fn debug_redacts_key() {
+        let cs = ConnectionString::parse("HostName=h.azure-devices.net;DeviceId=d;SharedAccessKey=c2VjcmV0").unwrap();
+        let text = format!("{:?}", cs);
+        assert!(!text.contains("c2VjcmV0"));
+        assert!(text.contains("<redacted>"));
+        let text = format!("{:?}", cs.get_credential());
+        assert!(!text.contains("c2VjcmV0"));
+        assert!(text.contains("SharedAccessKey"));
+    }
+}
@@ -0,0 +1,805 @@
+pub use crate::az_core::*;
+pub use crate::az_return_codes::AzReturnCode;
+use crate::az_iot::{grow_until_fits, url_encoded_len, write_c_string, write_span, IotStatus, FIXED_TEXT_ESTIMATE};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use azsys;
+
+pub struct ProvisioningClientBuilder<'a> {
+    global_device_hostname: Option<&'a str>,
+    id_scope: Option<&'a str>,
+    registration_id: Option<&'a str>,
+    client_options: Option<ProvisioningClientOptions>,
+}
+
+pub struct ProvisioningClient {
+    inner: azsys::az_iot_provisioning_client,
+    global_device_hostname: String,
+    id_scope: String,
+    registration_id: String,
+}
+
+impl<'a> ProvisioningClientBuilder<'a> {
+    pub fn new() -> ProvisioningClientBuilder<'a> {
+        ProvisioningClientBuilder {
+            global_device_hostname: Option::None,
+            id_scope: Option::None,
+            registration_id: Option::None,
+            client_options: Option::None,
+        }
+    }
+
+    pub fn global_device_hostname(
+        &mut self,
+        global_device_hostname: &'a str,
+    ) -> &mut ProvisioningClientBuilder<'a> {
+        self.global_device_hostname = Option::Some(global_device_hostname);
+        self
+    }
+
+    pub fn id_scope(&mut self, id_scope: &'a str) -> &mut ProvisioningClientBuilder<'a> {
+        self.id_scope = Option::Some(id_scope);
+        self
+    }
+
+    pub fn registration_id(&mut self, registration_id: &'a str) -> &mut ProvisioningClientBuilder<'a> {
+        self.registration_id = Option::Some(registration_id);
+        self
+    }
+
+    pub fn client_options(
+        &mut self,
+        client_options: ProvisioningClientOptions,
+    ) -> &mut ProvisioningClientBuilder<'a> {
+        self.client_options = Option::Some(client_options);
+        self
+    }
+
+    pub fn finalize(&mut self) -> Result<ProvisioningClient, AzReturnCode> {
+        if self.global_device_hostname.is_none()
+            || self.id_scope.is_none()
+            || self.registration_id.is_none()
+        {
+            return Err(AzReturnCode::AzResultCoreErrorArg);
+        }
+
+        ProvisioningClient::new(
+            self.global_device_hostname.unwrap(),
+            self.id_scope.unwrap(),
+            self.registration_id.unwrap(),
+            self.client_options.take(),
+        )
+    }
+}
+
+impl<'a> Default for ProvisioningClientBuilder<'a> {
+    fn default() -> Self {
+        ProvisioningClientBuilder::new()
+    }
+}
+
+impl ProvisioningClient {
+    pub const GLOBAL_DEVICE_HOSTNAME: &'static str = "global.azure-devices-provisioning.net";
+
+    pub fn new(
+        global_device_hostname: &str,
+        id_scope: &str,
+        registration_id: &str,
+        options: Option<ProvisioningClientOptions>,
+    ) -> Result<ProvisioningClient, AzReturnCode> {
+        let options_work: *const azsys::az_iot_provisioning_client_options = match &options {
+            Some(o) => &o.inner,
+            None => core::ptr::null(),
+        };
+
+        // The C client only holds spans so it keeps copies of the strings they refer to
+        let mut client: ProvisioningClient = ProvisioningClient::new_empty();
+        client.global_device_hostname = global_device_hostname.to_string();
+        client.id_scope = id_scope.to_string();
+        client.registration_id = registration_id.to_string();
+        let rc = unsafe {
+            azsys::az_iot_provisioning_client_init(
+                &mut client.inner,
+                AzSpan::from(client.global_device_hostname.as_str()).as_raw(),
+                AzSpan::from(client.id_scope.as_str()).as_raw(),
+                AzSpan::from(client.registration_id.as_str()).as_raw(),
+                options_work,
+            )
+        };
+
+        if rc != azsys::az_result_core_AZ_OK {
+            Err(AzReturnCode::from_i32(rc))
+        } else {
+            Ok(client)
+        }
+    }
+
+    pub fn new_empty() -> ProvisioningClient {
+        ProvisioningClient {
+            inner: azsys::az_iot_provisioning_client {
+                _internal: azsys::az_iot_provisioning_client__bindgen_ty_1 {
+                    global_device_endpoint: AzSpan::empty().as_raw(),
+                    id_scope: AzSpan::empty().as_raw(),
+                    registration_id: AzSpan::empty().as_raw(),
+                    options: ProvisioningClientOptions::default_new().inner,
+                },
+            },
+            global_device_hostname: String::new(),
+            id_scope: String::new(),
+            registration_id: String::new(),
+        }
+    }
+
+    pub fn get_global_device_hostname(&self) -> &str {
+        &self.global_device_hostname
+    }
+
+    pub fn get_id_scope(&self) -> &str {
+        &self.id_scope
+    }
+
+    pub fn get_registration_id(&self) -> &str {
+        &self.registration_id
+    }
+
+    fn identity_len(&self) -> usize {
+        self.global_device_hostname.len() + self.id_scope.len() + self.registration_id.len()
+    }
+
+    pub fn get_client_id(&self) -> Result<String, AzReturnCode> {
+        let capacity = self.registration_id.len() + FIXED_TEXT_ESTIMATE;
+        grow_until_fits(capacity, |result| self.ll_get_client_id(result))
+    }
+
+    pub fn ll_get_client_id(&self, result: &mut String) -> AzReturnCode {
+        let mut len: azsys::size_t = 0;
+        let len_ptr: *mut azsys::size_t = &mut len;
+
+        let rc = unsafe {
+            azsys::az_iot_provisioning_client_get_client_id(
+                &self.inner,
+                result.as_mut_vec().as_mut_ptr() as *mut _,
+                result.capacity() as azsys::size_t,
+                len_ptr,
+            )
+        };
+
+        if rc == azsys::az_result_core_AZ_OK {
+            unsafe { result.as_mut_vec().set_len(len as usize) };
+        }
+
+        AzReturnCode::from_i32(rc)
+    }
+
+    /// Writes the client id into buffer without allocating.
+    pub fn get_client_id_into<'b>(&self, buffer: &'b mut [u8]) -> Result<&'b str, AzReturnCode> {
+        write_c_string(buffer, |ptr, size, len| unsafe {
+            azsys::az_iot_provisioning_client_get_client_id(&self.inner, ptr, size, len)
+        })
+    }
+
+    pub fn get_user_name(&self) -> Result<String, AzReturnCode> {
+        let capacity = self.identity_len() + FIXED_TEXT_ESTIMATE;
+        grow_until_fits(capacity, |result| self.ll_get_user_name(result))
+    }
+
+    pub fn ll_get_user_name(&self, result: &mut String) -> AzReturnCode {
+        let mut len: azsys::size_t = 0;
+        let len_ptr: *mut azsys::size_t = &mut len;
+
+        let rc = unsafe {
+            azsys::az_iot_provisioning_client_get_user_name(
+                &self.inner,
+                result.as_mut_vec().as_mut_ptr() as *mut _,
+                result.capacity() as azsys::size_t,
+                len_ptr,
+            )
+        };
+
+        if rc == azsys::az_result_core_AZ_OK {
+            unsafe { result.as_mut_vec().set_len(len as usize) };
+        }
+
+        AzReturnCode::from_i32(rc)
+    }
+
+    pub fn get_user_name_into<'b>(&self, buffer: &'b mut [u8]) -> Result<&'b str, AzReturnCode> {
+        write_c_string(buffer, |ptr, size, len| unsafe {
+            azsys::az_iot_provisioning_client_get_user_name(&self.inner, ptr, size, len)
+        })
+    }
+
+    pub fn get_register_subscribe_topic() -> &'static str {
+        static AZ_IOT_PROVISIONING_CLIENT_REGISTER_SUBSCRIBE_TOPIC: &str = "$dps/registrations/res/#";
+        AZ_IOT_PROVISIONING_CLIENT_REGISTER_SUBSCRIBE_TOPIC
+    }
+
+    pub fn register_get_publish_topic(&self) -> Result<String, AzReturnCode> {
+        grow_until_fits(FIXED_TEXT_ESTIMATE, |result| self.ll_register_get_publish_topic(result))
+    }
+
+    pub fn ll_register_get_publish_topic(&self, result: &mut String) -> AzReturnCode {
+        let mut len: azsys::size_t = 0;
+        let len_ptr: *mut azsys::size_t = &mut len;
+
+        let rc = unsafe {
+            azsys::az_iot_provisioning_client_register_get_publish_topic(
+                &self.inner,
+                result.as_mut_vec().as_mut_ptr() as *mut _,
+                result.capacity() as azsys::size_t,
+                len_ptr,
+            )
+        };
+
+        if rc == azsys::az_result_core_AZ_OK {
+            unsafe { result.as_mut_vec().set_len(len as usize) };
+        }
+
+        AzReturnCode::from_i32(rc)
+    }
+
+    pub fn register_get_publish_topic_into<'b>(&self, buffer: &'b mut [u8]) -> Result<&'b str, AzReturnCode> {
+        write_c_string(buffer, |ptr, size, len| unsafe {
+            azsys::az_iot_provisioning_client_register_get_publish_topic(&self.inner, ptr, size, len)
+        })
+    }
+
+    pub fn query_status_get_publish_topic(&self, operation_id: &str) -> Result<String, AzReturnCode> {
+        let capacity = operation_id.len() + FIXED_TEXT_ESTIMATE;
+        grow_until_fits(capacity, |result| self.ll_query_status_get_publish_topic(operation_id, result))
+    }
+
+    pub fn ll_query_status_get_publish_topic(&self, operation_id: &str, result: &mut String) -> AzReturnCode {
+        let mut len: azsys::size_t = 0;
+        let len_ptr: *mut azsys::size_t = &mut len;
+
+        let rc = unsafe {
+            azsys::az_iot_provisioning_client_query_status_get_publish_topic(
+                &self.inner,
+                AzSpan::from(operation_id).as_raw(),
+                result.as_mut_vec().as_mut_ptr() as *mut _,
+                result.capacity() as azsys::size_t,
+                len_ptr,
+            )
+        };
+
+        if rc == azsys::az_result_core_AZ_OK {
+            unsafe { result.as_mut_vec().set_len(len as usize) };
+        }
+
+        AzReturnCode::from_i32(rc)
+    }
+
+    pub fn query_status_get_publish_topic_into<'b>(
+        &self,
+        operation_id: &str,
+        buffer: &'b mut [u8],
+    ) -> Result<&'b str, AzReturnCode> {
+        write_c_string(buffer, |ptr, size, len| unsafe {
+            azsys::az_iot_provisioning_client_query_status_get_publish_topic(
+                &self.inner,
+                AzSpan::from(operation_id).as_raw(),
+                ptr,
+                size,
+                len,
+            )
+        })
+    }
+
+    pub fn parse_received_topic_and_payload<'a>(
+        &self,
+        topic: &'a str,
+        payload: &'a [u8],
+    ) -> Result<ProvisioningRegisterResponse<'a>, AzReturnCode> {
+        let payload_span = AzSpan::from(payload);
+        let mut response = azsys::az_iot_provisioning_client_register_response {
+            status: 0,
+            retry_after_seconds: 0,
+            operation_id: AzSpan::empty().as_raw(),
+            operation_status: AzSpan::empty().as_raw(),
+            registration_state: azsys::az_iot_provisioning_client_registration_state {
+                assigned_hub_hostname: AzSpan::empty().as_raw(),
+                device_id: AzSpan::empty().as_raw(),
+                error_code: 0,
+                extended_error_code: 0,
+                error_message: AzSpan::empty().as_raw(),
+                error_tracking_id: AzSpan::empty().as_raw(),
+                error_timestamp: AzSpan::empty().as_raw(),
+            },
+        };
+        let rc = unsafe {
+            azsys::az_iot_provisioning_client_parse_received_topic_and_payload(
+                &self.inner,
+                AzSpan::from(topic).as_raw(),
+                payload_span.as_raw(),
+                &mut response,
+            )
+        };
+
+        if rc != azsys::az_result_core_AZ_OK {
+            return Err(AzReturnCode::from_i32(rc));
+        }
+
+        let mut operation_status: azsys::az_iot_provisioning_client_operation_status =
+            azsys::az_iot_provisioning_client_operation_status_AZ_IOT_PROVISIONING_STATUS_UNASSIGNED;
+        let rc = unsafe {
+            azsys::az_iot_provisioning_client_parse_operation_status(&mut response, &mut operation_status)
+        };
+
+        if rc != azsys::az_result_core_AZ_OK {
+            return Err(AzReturnCode::from_i32(rc));
+        }
+
+        let operation_status = match operation_status {
+            azsys::az_iot_provisioning_client_operation_status_AZ_IOT_PROVISIONING_STATUS_ASSIGNING => {
+                ProvisioningOperationStatus::Assigning
+            }
+            azsys::az_iot_provisioning_client_operation_status_AZ_IOT_PROVISIONING_STATUS_ASSIGNED => {
+                ProvisioningOperationStatus::Assigned
+            }
+            azsys::az_iot_provisioning_client_operation_status_AZ_IOT_PROVISIONING_STATUS_FAILED => {
+                ProvisioningOperationStatus::Failed
+            }
+            azsys::az_iot_provisioning_client_operation_status_AZ_IOT_PROVISIONING_STATUS_DISABLED => {
+                ProvisioningOperationStatus::Disabled
+            }
+            _ => ProvisioningOperationStatus::Unassigned,
+        };
+        let state = &response.registration_state;
+
+        Ok(ProvisioningRegisterResponse {
+            status: response.status as u16,
+            retry_after_seconds: response.retry_after_seconds,
+            operation_id: payload_span.sub_span(response.operation_id)?,
+            operation_status,
+            assigned_hub_hostname: payload_span.sub_span(state.assigned_hub_hostname)?,
+            device_id: payload_span.sub_span(state.device_id)?,
+            error_code: state.error_code as u16,
+            extended_error_code: state.extended_error_code,
+            error_message: payload_span.sub_span(state.error_message)?,
+            error_tracking_id: payload_span.sub_span(state.error_tracking_id)?,
+            error_timestamp: payload_span.sub_span(state.error_timestamp)?,
+        })
+    }
+
+    pub fn get_sas_signature(&self, ttl: u64) -> Result<Vec<u8>, AzReturnCode> {
+        let capacity = self.identity_len() + FIXED_TEXT_ESTIMATE;
+        grow_until_fits(capacity, |result| self.ll_get_sas_signature(ttl, result))
+    }
+
+    pub fn ll_get_sas_signature(&self, ttl: u64, result: &mut Vec<u8>) -> AzReturnCode {
+        // Let the C SDK write into the whole allocation then trim to what it wrote
+        let capacity = result.capacity();
+        result.clear();
+        result.resize(capacity, 0);
+        let mut work = AzSpan::empty().as_raw();
+        let rc = unsafe {
+            azsys::az_iot_provisioning_client_sas_get_signature(
+                &self.inner,
+                ttl,
+                AzSpanMut::from(result.as_mut_slice()).as_raw(),
+                &mut work,
+            )
+        };
+
+        let written = if rc == azsys::az_result_core_AZ_OK {
+            match AzSpan::from(result.as_slice()).sub_span(work) {
+                Ok(span) => span.len(),
+                Err(e) => {
+                    result.clear();
+                    return e;
+                }
+            }
+        } else {
+            0
+        };
+        result.truncate(written);
+
+        AzReturnCode::from_i32(rc)
+    }
+
+    pub fn get_sas_signature_into<'b>(&self, ttl: u64, buffer: &'b mut [u8]) -> Result<&'b [u8], AzReturnCode> {
+        write_span(buffer, |span, out| unsafe {
+            azsys::az_iot_provisioning_client_sas_get_signature(&self.inner, ttl, span, out)
+        })
+    }
+
+    pub fn get_sas_password(&self, ttl: u64, sas: &str, key_name: Option<&str>) -> Result<String, AzReturnCode> {
+        let capacity = self.identity_len() + url_encoded_len(sas) + key_name.map_or(0, str::len) + FIXED_TEXT_ESTIMATE;
+        grow_until_fits(capacity, |result| self.ll_get_sas_password(ttl, sas, key_name, result))
+    }
+
+    pub fn ll_get_sas_password(
+        &self,
+        ttl: u64,
+        sas: &str,
+        key_name: Option<&str>,
+        result: &mut String,
+    ) -> AzReturnCode {
+        let key_name_span = match key_name {
+            Some(k) => AzSpan::from(k).as_raw(),
+            None => AzSpan::empty().as_raw(),
+        };
+        let mut len: azsys::size_t = 0;
+        let len_ptr: *mut azsys::size_t = &mut len;
+        let rc = unsafe {
+            azsys::az_iot_provisioning_client_sas_get_password(
+                &self.inner,
+                AzSpan::from(sas).as_raw(),
+                ttl,
+                key_name_span,
+                result.as_mut_vec().as_mut_ptr() as *mut _,
+                result.capacity() as azsys::size_t,
+                len_ptr,
+            )
+        };
+
+        if rc == azsys::az_result_core_AZ_OK {
+            unsafe { result.as_mut_vec().set_len(len as usize) };
+        }
+
+        AzReturnCode::from_i32(rc)
+    }
+
+    pub fn get_sas_password_into<'b>(
+        &self,
+        ttl: u64,
+        sas: &str,
+        key_name: Option<&str>,
+        buffer: &'b mut [u8],
+    ) -> Result<&'b str, AzReturnCode> {
+        let key_name_span = match key_name {
+            Some(k) => AzSpan::from(k).as_raw(),
+            None => AzSpan::empty().as_raw(),
+        };
+
+        write_c_string(buffer, |ptr, size, len| unsafe {
+            azsys::az_iot_provisioning_client_sas_get_password(
+                &self.inner,
+                AzSpan::from(sas).as_raw(),
+                ttl,
+                key_name_span,
+                ptr,
+                size,
+                len,
+            )
+        })
+    }
+}
+
+pub struct ProvisioningClientOptions {
+    inner: azsys::az_iot_provisioning_client_options,
+}
+
+impl ProvisioningClientOptions {
+    pub fn default_new() -> ProvisioningClientOptions {
+        ProvisioningClientOptions {
+            inner: unsafe { azsys::az_iot_provisioning_client_options_default() },
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ProvisioningOperationStatus {
+    Unassigned,
+    Assigning,
+    Assigned,
+    Failed,
+    Disabled,
+}
+
+impl ProvisioningOperationStatus {
+    /// Returns true once the service will no longer update the registration and
+    /// polling with the query status topic can stop.
+    pub fn is_complete(&self) -> bool {
+        matches!(
+            self,
+            ProvisioningOperationStatus::Assigned
+                | ProvisioningOperationStatus::Failed
+                | ProvisioningOperationStatus::Disabled
+        )
+    }
+}
+
+pub struct ProvisioningRegisterResponse<'a> {
+    status: u16,
+    retry_after_seconds: u32,
+    operation_id: AzSpan<'a>,
+    operation_status: ProvisioningOperationStatus,
+    assigned_hub_hostname: AzSpan<'a>,
+    device_id: AzSpan<'a>,
+    error_code: u16,
+    extended_error_code: u32,
+    error_message: AzSpan<'a>,
+    error_tracking_id: AzSpan<'a>,
+    error_timestamp: AzSpan<'a>,
+}
+
+impl<'a> ProvisioningRegisterResponse<'a> {
+    pub fn new_empty() -> ProvisioningRegisterResponse<'a> {
+        ProvisioningRegisterResponse {
+            status: 0,
+            retry_after_seconds: 0,
+            operation_id: AzSpan::empty(),
+            operation_status: ProvisioningOperationStatus::Unassigned,
+            assigned_hub_hostname: AzSpan::empty(),
+            device_id: AzSpan::empty(),
+            error_code: 0,
+            extended_error_code: 0,
+            error_message: AzSpan::empty(),
+            error_tracking_id: AzSpan::empty(),
+            error_timestamp: AzSpan::empty(),
+        }
+    }
+
+    pub fn get_status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn get_iot_status(&self) -> IotStatus {
+        IotStatus::from_u16(self.status)
+    }
+
+    pub fn get_retry_after_seconds(&self) -> u32 {
+        self.retry_after_seconds
+    }
+
+    pub fn get_operation_id(&self) -> Result<&'a str, AzReturnCode> {
+        Ok(self.operation_id.as_str()?)
+    }
+
+    pub fn get_operation_status(&self) -> ProvisioningOperationStatus {
+        self.operation_status
+    }
+
+    pub fn get_assigned_hub_hostname(&self) -> Result<&'a str, AzReturnCode> {
+        Ok(self.assigned_hub_hostname.as_str()?)
+    }
+
+    pub fn get_device_id(&self) -> Result<&'a str, AzReturnCode> {
+        Ok(self.device_id.as_str()?)
+    }
+
+    pub fn get_error_code(&self) -> u16 {
+        self.error_code
+    }
+
+    pub fn get_error_iot_status(&self) -> IotStatus {
+        IotStatus::from_u16(self.error_code)
+    }
+
+    pub fn get_extended_error_code(&self) -> u32 {
+        self.extended_error_code
+    }
+
+    pub fn get_error_message(&self) -> Result<&'a str, AzReturnCode> {
+        Ok(self.error_message.as_str()?)
+    }
+
+    pub fn get_error_tracking_id(&self) -> Result<&'a str, AzReturnCode> {
+        Ok(self.error_tracking_id.as_str()?)
+    }
+
+    pub fn get_error_timestamp(&self) -> Result<&'a str, AzReturnCode> {
+        Ok(self.error_timestamp.as_str()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::az_iot_retry::{NoJitter, RetryPolicy};
+    static GLOBAL_DEVICE_HOSTNAME: &str = "global.azure-devices-provisioning.net";
+    static ID_SCOPE: &str = "0ne00000001";
+    static REGISTRATION_ID: &str = "test1";
+    static OPERATION_ID: &str = "4.d0a671905ea5b2c8.42d78160-4c78-479e-8be7-61d5e55dac0d";
+    #[test]
+    fn provisioning_client_builder() {
+        let client = ProvisioningClientBuilder::new()
+            .global_device_hostname(GLOBAL_DEVICE_HOSTNAME)
+            .id_scope(ID_SCOPE)
+            .registration_id(REGISTRATION_ID)
+            .finalize()
+            .unwrap();
+        assert_eq!(client.get_client_id().unwrap(), REGISTRATION_ID);
+    }
+    #[test]
+    fn provisioning_client_builder_missing_parameters() {
+        let result = ProvisioningClientBuilder::new()
+            .global_device_hostname(GLOBAL_DEVICE_HOSTNAME)
+            .id_scope(ID_SCOPE)
+            .finalize();
+        assert!(matches!(result, Err(AzReturnCode::AzResultCoreErrorArg)));
+    }
+    #[test]
+    fn provisioning_client_from_temporary_strings() {
+        let client = {
+            let id_scope = String::from(ID_SCOPE);
+            let registration_id = String::from(REGISTRATION_ID);
+            ProvisioningClient::new(GLOBAL_DEVICE_HOSTNAME, &id_scope, &registration_id, Option::None).unwrap()
+        };
+        assert_eq!(client.get_id_scope(), ID_SCOPE);
+        assert_eq!(client.get_registration_id(), REGISTRATION_ID);
+        assert_eq!(client.get_client_id().unwrap(), REGISTRATION_ID);
+    }
+    #[test]
+    fn provisioning_client_get_client_id() {
+        let client =
+            ProvisioningClient::new(GLOBAL_DEVICE_HOSTNAME, ID_SCOPE, REGISTRATION_ID, Option::None).unwrap();
+        let mut out = String::with_capacity(200);
+        let rc = client.ll_get_client_id(&mut out);
+        assert_eq!(rc, AzReturnCode::AzResultCoreOk);
+        assert_eq!(out, REGISTRATION_ID);
+    }
+    #[test]
+    fn provisioning_client_get_user_name() {
+        let client =
+            ProvisioningClient::new(GLOBAL_DEVICE_HOSTNAME, ID_SCOPE, REGISTRATION_ID, Option::None).unwrap();
+        let user_name = format!(
+            "{}/registrations/{}/api-version=2019-03-31&ClientVersion=c%2F1.1.0-beta.2",
+            ID_SCOPE, REGISTRATION_ID
+        );
+        assert_eq!(client.get_user_name().unwrap(), user_name);
+    }
+    #[test]
+    fn provisioning_client_get_register_subscribe_topic() {
+        assert_eq!(
+            ProvisioningClient::get_register_subscribe_topic(),
+            "$dps/registrations/res/#"
+        );
+    }
+    #[test]
+    fn provisioning_client_register_get_publish_topic() {
+        let client =
+            ProvisioningClient::new(GLOBAL_DEVICE_HOSTNAME, ID_SCOPE, REGISTRATION_ID, Option::None).unwrap();
+        assert_eq!(
+            client.register_get_publish_topic().unwrap(),
+            "$dps/registrations/PUT/iotdps-register/?$rid=1"
+        );
+    }
+    #[test]
+    fn provisioning_client_query_status_get_publish_topic() {
+        let client =
+            ProvisioningClient::new(GLOBAL_DEVICE_HOSTNAME, ID_SCOPE, REGISTRATION_ID, Option::None).unwrap();
+        assert_eq!(
+            client.query_status_get_publish_topic(OPERATION_ID).unwrap(),
+            format!(
+                "$dps/registrations/GET/iotdps-get-operationstatus/?$rid=1&operationId={}",
+                OPERATION_ID
+            )
+        );
+    }
+    #[test]
+    fn provisioning_client_get_sas_signature() {
+        let client =
+            ProvisioningClient::new(GLOBAL_DEVICE_HOSTNAME, ID_SCOPE, REGISTRATION_ID, Option::None).unwrap();
+        let signature = ID_SCOPE.to_string() + "%2Fregistrations%2F" + REGISTRATION_ID + "\n100";
+        assert_eq!(
+            String::from_utf8_lossy(&client.get_sas_signature(100).unwrap()),
+            signature
+        );
+    }
+    #[test]
+    fn provisioning_client_get_sas_password() {
+        let client =
+            ProvisioningClient::new(GLOBAL_DEVICE_HOSTNAME, ID_SCOPE, REGISTRATION_ID, Option::None).unwrap();
+        let sas = "NotReallyASASToken";
+        let password = "SharedAccessSignature sr=".to_string()
+            + ID_SCOPE
+            + "%2Fregistrations%2F"
+            + REGISTRATION_ID
+            + "&sig="
+            + sas
+            + "&se=100";
+        assert_eq!(client.get_sas_password(100, sas, Option::None).unwrap(), password);
+    }
+    #[test]
+    fn provisioning_client_get_sas_password_key_name() {
+        let client =
+            ProvisioningClient::new(GLOBAL_DEVICE_HOSTNAME, ID_SCOPE, REGISTRATION_ID, Option::None).unwrap();
+        let sas = "NotReallyASASToken";
+        let password = "SharedAccessSignature sr=".to_string()
+            + ID_SCOPE
+            + "%2Fregistrations%2F"
+            + REGISTRATION_ID
+            + "&sig="
+            + sas
+            + "&se=100&skn=registration";
+        assert_eq!(client.get_sas_password(100, sas, Option::Some("registration")).unwrap(), password);
+        let mut out = String::with_capacity(200);
+        let rc = client.ll_get_sas_password(100, sas, Option::Some("registration"), &mut out);
+        assert_eq!(rc, AzReturnCode::AzResultCoreOk);
+        assert_eq!(out, password);
+    }
+    #[test]
+    fn provisioning_client_get_into_static_buffers() {
+        let client =
+            ProvisioningClient::new(GLOBAL_DEVICE_HOSTNAME, ID_SCOPE, REGISTRATION_ID, Option::None).unwrap();
+        let mut buffer = [0u8; 256];
+        assert_eq!(client.get_client_id_into(&mut buffer), Ok(REGISTRATION_ID));
+        assert_eq!(client.get_user_name_into(&mut buffer).map(String::from), client.get_user_name());
+        assert_eq!(
+            client.register_get_publish_topic_into(&mut buffer),
+            Ok("$dps/registrations/PUT/iotdps-register/?$rid=1")
+        );
+        assert_eq!(
+            client.query_status_get_publish_topic_into(OPERATION_ID, &mut buffer).map(String::from),
+            client.query_status_get_publish_topic(OPERATION_ID)
+        );
+        assert_eq!(
+            client.get_sas_signature_into(100, &mut buffer).map(|s| s.to_vec()),
+            client.get_sas_signature(100)
+        );
+        assert_eq!(
+            client.get_sas_password_into(100, "sig", Option::None, &mut buffer).map(String::from),
+            client.get_sas_password(100, "sig", Option::None)
+        );
+        let mut buffer = [0u8; 4];
+        assert_eq!(
+            client.register_get_publish_topic_into(&mut buffer),
+            Err(AzReturnCode::AzResultCoreErrorNotEnoughSpace)
+        );
+    }
+    #[test]
+    fn provisioning_client_parse_assigning() {
+        let client =
+            ProvisioningClient::new(GLOBAL_DEVICE_HOSTNAME, ID_SCOPE, REGISTRATION_ID, Option::None).unwrap();
+        let topic = "$dps/registrations/res/202/?$rid=1&retry-after=3";
+        let payload = format!("{{\"operationId\":\"{}\",\"status\":\"assigning\"}}", OPERATION_ID);
+        let response = client
+            .parse_received_topic_and_payload(topic, payload.as_bytes())
+            .unwrap();
+        assert_eq!(response.get_status(), 202);
+        assert_eq!(response.get_iot_status(), IotStatus::Accepted);
+        assert!(response.get_iot_status().is_success());
+        assert_eq!(response.get_retry_after_seconds(), 3);
+        assert_eq!(response.get_operation_id(), Ok(OPERATION_ID));
+        assert_eq!(response.get_operation_status(), ProvisioningOperationStatus::Assigning);
+        assert!(!response.get_operation_status().is_complete());
+        assert_eq!(response.get_assigned_hub_hostname(), Ok(""));
+    }
+    #[test]
+    fn provisioning_register_response_retry() {
+        let client =
+            ProvisioningClient::new(GLOBAL_DEVICE_HOSTNAME, ID_SCOPE, REGISTRATION_ID, Option::None).unwrap();
+        let topic = "$dps/registrations/res/202/?$rid=1&retry-after=3";
+        let payload = format!("{{\"operationId\":\"{}\",\"status\":\"assigning\"}}", OPERATION_ID);
+        let response = client
+            .parse_received_topic_and_payload(topic, payload.as_bytes())
+            .unwrap();
+        let mut policy = RetryPolicy::new(NoJitter);
+        assert_eq!(policy.register_response_delay(1, 0, &response), Option::Some(3_000));
+        assert_eq!(policy.backoff().next_for_register_response(&response, 0), Option::Some(3_000));
+    }
+    #[test]
+    fn provisioning_client_parse_assigned() {
+        let client =
+            ProvisioningClient::new(GLOBAL_DEVICE_HOSTNAME, ID_SCOPE, REGISTRATION_ID, Option::None).unwrap();
+        let topic = "$dps/registrations/res/200/?$rid=1";
+        let payload = format!(
+            "{{\"operationId\":\"{}\",\"status\":\"assigned\",\"registrationState\":{{\
+             \"registrationId\":\"{}\",\"assignedHub\":\"contoso.azure-devices.net\",\
+             \"deviceId\":\"my-device\",\"status\":\"assigned\",\"substatus\":\"initialAssignment\",\
+             \"lastUpdatedDateTimeUtc\":\"2020-04-10T03:11:13.0276997Z\",\"etag\":\"IjYxMDA=\"}}}}",
+            OPERATION_ID, REGISTRATION_ID
+        );
+        let response = client
+            .parse_received_topic_and_payload(topic, payload.as_bytes())
+            .unwrap();
+        assert_eq!(response.get_status(), 200);
+        assert_eq!(response.get_operation_id(), Ok(OPERATION_ID));
+        assert_eq!(response.get_operation_status(), ProvisioningOperationStatus::Assigned);
+        assert!(response.get_operation_status().is_complete());
+        assert_eq!(response.get_assigned_hub_hostname(), Ok("contoso.azure-devices.net"));
+        assert_eq!(response.get_device_id(), Ok("my-device"));
+    }
+    #[test]
+    fn provisioning_client_parse_no_match() {
+        let client =
+            ProvisioningClient::new(GLOBAL_DEVICE_HOSTNAME, ID_SCOPE, REGISTRATION_ID, Option::None).unwrap();
+        assert!(matches!(
+            client.parse_received_topic_and_payload("$iothub/methods/POST/reboot/?$rid=1", b"{}"),
+            Err(AzReturnCode::AzResultIoTErrorTopicNoMatch)
+        ));
+    }
+}
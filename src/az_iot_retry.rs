@@ -0,0 +1,431 @@
+pub use crate::az_iot::{HubClient, IotStatus};
+pub use crate::az_iot_provisioning::ProvisioningRegisterResponse;
+pub use crate::az_return_codes::AzReturnCode;
+use alloc::vec::Vec;
+use core::cmp;
+
+/// Supplies the random jitter added to each retry delay so that devices disconnected at the
+/// same time do not all reconnect at once. Implement it over whatever random source the
+/// platform has, or pass a closure returning u32.
+pub trait RetryRng {
+    fn next_u32(&mut self) -> u32;
+}
+
+impl<F> RetryRng for F
+where
+    F: FnMut() -> u32,
+{
+    fn next_u32(&mut self) -> u32 {
+        self()
+    }
+}
+
+/// Adds no jitter, for tests or when delays must be predictable.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoJitter;
+
+impl RetryRng for NoJitter {
+    fn next_u32(&mut self) -> u32 {
+        0
+    }
+}
+
+/// How a RetryPolicy treats a particular IotStatus. By default Accepted, which DPS sends while
+/// it is still assigning a device, and the statuses IotStatus::is_retriable accepts, such as
+/// Throttled and the 5xx errors, are RetryAfter and everything else is Never.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum StatusRetry {
+    /// Give up on this status
+    Never,
+    /// Retry with the policy's exponential backoff
+    Backoff,
+    /// Retry after the delay the service asked for, such as the retry-after DPS sends with
+    /// its responses, falling back to the backoff when there is none
+    RetryAfter,
+    /// Retry after a fixed number of milliseconds, ignoring any retry-after
+    Fixed(u32),
+}
+
+pub struct RetryPolicy<R: RetryRng> {
+    min_delay_msec: u32,
+    max_delay_msec: u32,
+    max_jitter_msec: u32,
+    max_attempts: Option<u16>,
+    status_overrides: Vec<(IotStatus, StatusRetry)>,
+    code_overrides: Vec<(AzReturnCode, bool)>,
+    rng: R,
+}
+
+impl<R: RetryRng> RetryPolicy<R> {
+    pub const DEFAULT_MIN_DELAY_MSEC: u32 = 1_000;
+    pub const DEFAULT_MAX_DELAY_MSEC: u32 = 100_000;
+    pub const DEFAULT_MAX_JITTER_MSEC: u32 = 5_000;
+
+    /// Creates a policy with the default delays that retries indefinitely.
+    pub fn new(rng: R) -> RetryPolicy<R> {
+        RetryPolicy {
+            min_delay_msec: Self::DEFAULT_MIN_DELAY_MSEC,
+            max_delay_msec: Self::DEFAULT_MAX_DELAY_MSEC,
+            max_jitter_msec: Self::DEFAULT_MAX_JITTER_MSEC,
+            max_attempts: Option::None,
+            status_overrides: Vec::new(),
+            code_overrides: Vec::new(),
+            rng,
+        }
+    }
+
+    pub fn min_delay(mut self, msec: u32) -> RetryPolicy<R> {
+        self.min_delay_msec = msec;
+        self
+    }
+
+    pub fn max_delay(mut self, msec: u32) -> RetryPolicy<R> {
+        self.max_delay_msec = msec;
+        self
+    }
+
+    /// Jitter is chosen between zero and msec inclusive.
+    pub fn max_jitter(mut self, msec: u32) -> RetryPolicy<R> {
+        self.max_jitter_msec = msec;
+        self
+    }
+
+    pub fn max_attempts(mut self, attempts: u16) -> RetryPolicy<R> {
+        self.max_attempts = Option::Some(attempts);
+        self
+    }
+
+    /// Replaces the default handling of status, for example
+    /// `status_override(IotStatus::Throttled, StatusRetry::RetryAfter)`.
+    pub fn status_override(mut self, status: IotStatus, retry: StatusRetry) -> RetryPolicy<R> {
+        self.status_overrides.retain(|(s, _)| *s != status);
+        self.status_overrides.push((status, retry));
+        self
+    }
+
+    /// Replaces the default answer should_retry gives for rc.
+    pub fn code_override(mut self, rc: AzReturnCode, retry: bool) -> RetryPolicy<R> {
+        self.code_overrides.retain(|(c, _)| *c != rc);
+        self.code_overrides.push((rc, retry));
+        self
+    }
+
+    pub fn get_min_delay(&self) -> u32 {
+        self.min_delay_msec
+    }
+
+    pub fn get_max_delay(&self) -> u32 {
+        self.max_delay_msec
+    }
+
+    pub fn get_max_jitter(&self) -> u32 {
+        self.max_jitter_msec
+    }
+
+    pub fn get_max_attempts(&self) -> Option<u16> {
+        self.max_attempts
+    }
+
+    pub fn get_status_override(&self, status: IotStatus) -> Option<StatusRetry> {
+        self.status_overrides.iter().find(|(s, _)| *s == status).map(|(_, r)| *r)
+    }
+
+    /// The treatment status gets, from status_override if there is one or the default otherwise.
+    pub fn get_status_retry(&self, status: IotStatus) -> StatusRetry {
+        match self.get_status_override(status) {
+            Option::Some(retry) => retry,
+            Option::None if status == IotStatus::Accepted || status.is_retriable() => StatusRetry::RetryAfter,
+            Option::None => StatusRetry::Never,
+        }
+    }
+
+    pub fn get_code_override(&self, rc: AzReturnCode) -> Option<bool> {
+        self.code_overrides.iter().find(|(c, _)| *c == rc).map(|(_, r)| *r)
+    }
+
+    /// Unless overridden with code_override, only Canceled and the HTTP pipeline's transport
+    /// failures (HttpAdapter, HttpResponseCouldntResolveHost and HttpCorruptResponseHeader) are
+    /// retried. The MQTT hub and provisioning APIs report transient failures through the status
+    /// of their responses instead, see should_retry_status and next_for_register_response.
+    pub fn should_retry(&self, rc: AzReturnCode) -> bool {
+        match self.get_code_override(rc) {
+            Option::Some(retry) => retry,
+            Option::None => matches!(
+                rc,
+                AzReturnCode::AzResultCoreErrorCanceled
+                    | AzReturnCode::AzResultCoreErrorHttpAdapter
+                    | AzReturnCode::AzResultCoreErrorHttpResponseCouldntResolveHost
+                    | AzReturnCode::AzResultCoreErrorHttpCorruptResponseHeader
+            ),
+        }
+    }
+
+    pub fn should_retry_status(&self, status: IotStatus) -> bool {
+        self.get_status_retry(status) != StatusRetry::Never
+    }
+
+    /// Exponential backoff for attempt, counting from one, less the time the failed operation
+    /// already took.
+    pub fn delay(&mut self, attempt: u16, operation_msec: u32) -> u32 {
+        let jitter = match self.max_jitter_msec {
+            0 => 0,
+            max => self.rng.next_u32() % (to_msec(max) as u32 + 1),
+        };
+
+        let delay = HubClient::calculate_retry_delay(
+            to_msec(operation_msec),
+            cmp::min(cmp::max(attempt, 1), i16::MAX as u16 - 1) as i16,
+            to_msec(self.min_delay_msec),
+            to_msec(self.max_delay_msec),
+            jitter as i32,
+        );
+        cmp::max(delay, 0) as u32
+    }
+
+    /// Delay in milliseconds before retrying a request the service answered with status, or
+    /// None when it should not be retried. retry_after_seconds is the delay the service asked
+    /// for, if any, in the seconds DPS reports it in.
+    pub fn status_delay(
+        &mut self,
+        attempt: u16,
+        operation_msec: u32,
+        status: IotStatus,
+        retry_after_seconds: Option<u32>,
+    ) -> Option<u32> {
+        match (self.get_status_retry(status), retry_after_seconds) {
+            (StatusRetry::Never, _) => Option::None,
+            (StatusRetry::Fixed(msec), _) => Option::Some(msec),
+            (StatusRetry::RetryAfter, Option::Some(seconds)) => Option::Some(seconds.saturating_mul(1_000)),
+            _ => Option::Some(self.delay(attempt, operation_msec)),
+        }
+    }
+
+    /// As status_delay for a DPS register or query status response. None once the registration
+    /// is complete, otherwise the response's retry-after is used when the service sent one.
+    pub fn register_response_delay(
+        &mut self,
+        attempt: u16,
+        operation_msec: u32,
+        response: &ProvisioningRegisterResponse,
+    ) -> Option<u32> {
+        let (status, retry_after_seconds) = register_response_status(response)?;
+        self.status_delay(attempt, operation_msec, status, retry_after_seconds)
+    }
+
+    /// Starts a sequence of retries. Each delay the returned iterator yields counts as an
+    /// attempt, and it ends once max_attempts have been made.
+    pub fn backoff(&mut self) -> Backoff<'_, R> {
+        Backoff {
+            policy: self,
+            attempt: 0,
+        }
+    }
+}
+
+pub struct Backoff<'a, R: RetryRng> {
+    policy: &'a mut RetryPolicy<R>,
+    attempt: u16,
+}
+
+impl<'a, R: RetryRng> Backoff<'a, R> {
+    /// Number of retries made so far.
+    pub fn get_attempt(&self) -> u16 {
+        self.attempt
+    }
+
+    /// Starts again from the shortest delay, typically after a successful connection.
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    /// Delay before the next retry allowing for the time the failed operation took, or None
+    /// when the attempts are used up.
+    pub fn next_after(&mut self, operation_msec: u32) -> Option<u32> {
+        self.next_attempt()?;
+        Option::Some(self.policy.delay(self.attempt, operation_msec))
+    }
+
+    /// As next_after for a request the service failed with status, applying the policy's
+    /// decision for that status.
+    pub fn next_for_status(
+        &mut self,
+        status: IotStatus,
+        retry_after_seconds: Option<u32>,
+        operation_msec: u32,
+    ) -> Option<u32> {
+        if !self.policy.should_retry_status(status) {
+            return Option::None;
+        }
+        self.next_attempt()?;
+        self.policy.status_delay(self.attempt, operation_msec, status, retry_after_seconds)
+    }
+
+    /// As next_for_status for a DPS register or query status response, ending the sequence
+    /// once the registration is complete.
+    pub fn next_for_register_response(
+        &mut self,
+        response: &ProvisioningRegisterResponse,
+        operation_msec: u32,
+    ) -> Option<u32> {
+        let (status, retry_after_seconds) = register_response_status(response)?;
+        self.next_for_status(status, retry_after_seconds, operation_msec)
+    }
+
+    fn next_attempt(&mut self) -> Option<u16> {
+        if let Option::Some(max) = self.policy.max_attempts {
+            if self.attempt >= max {
+                return Option::None;
+            }
+        }
+        self.attempt = self.attempt.saturating_add(1);
+        Option::Some(self.attempt)
+    }
+}
+
+impl<'a, R: RetryRng> Iterator for Backoff<'a, R> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        self.next_after(0)
+    }
+}
+
+// The status and retry-after of a DPS response that still needs retrying or polling, None
+// once the registration is complete
+fn register_response_status(response: &ProvisioningRegisterResponse) -> Option<(IotStatus, Option<u32>)> {
+    let status = response.get_iot_status();
+
+    if status.is_success() && response.get_operation_status().is_complete() {
+        return Option::None;
+    }
+
+    let retry_after_seconds = match response.get_retry_after_seconds() {
+        0 => Option::None,
+        seconds => Option::Some(seconds),
+    };
+    Option::Some((status, retry_after_seconds))
+}
+
+// The C SDK takes signed milliseconds and treats anything outside 0..=i32::MAX - 1 as a
+// precondition failure
+fn to_msec(value: u32) -> i32 {
+    cmp::min(value, i32::MAX as u32 - 1) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn retry_policy_defaults() {
+        let policy = RetryPolicy::new(NoJitter);
+        assert_eq!(policy.get_min_delay(), RetryPolicy::<NoJitter>::DEFAULT_MIN_DELAY_MSEC);
+        assert_eq!(policy.get_max_delay(), RetryPolicy::<NoJitter>::DEFAULT_MAX_DELAY_MSEC);
+        assert_eq!(policy.get_max_jitter(), RetryPolicy::<NoJitter>::DEFAULT_MAX_JITTER_MSEC);
+        assert_eq!(policy.get_max_attempts(), Option::None);
+        assert_eq!(policy.get_status_override(IotStatus::Throttled), Option::None);
+    }
+    #[test]
+    fn retry_policy_should_retry() {
+        let policy = RetryPolicy::new(NoJitter);
+        assert!(policy.should_retry(AzReturnCode::AzResultCoreErrorHttpAdapter));
+        assert!(!policy.should_retry(AzReturnCode::AzResultCoreOk));
+        assert!(!policy.should_retry(AzReturnCode::AzResultCoreErrorArg));
+        assert!(!policy.should_retry(AzReturnCode::AzResultCoreErrorNotEnoughSpace));
+    }
+    #[test]
+    fn retry_policy_code_overrides() {
+        let policy = RetryPolicy::new(NoJitter)
+            .code_override(AzReturnCode::AzResultCoreErrorNotEnoughSpace, true)
+            .code_override(AzReturnCode::AzResultCoreErrorHttpAdapter, true)
+            .code_override(AzReturnCode::AzResultCoreErrorHttpAdapter, false);
+        assert!(policy.should_retry(AzReturnCode::AzResultCoreErrorNotEnoughSpace));
+        assert!(!policy.should_retry(AzReturnCode::AzResultCoreErrorHttpAdapter));
+        assert!(policy.should_retry(AzReturnCode::AzResultCoreErrorCanceled));
+        assert_eq!(policy.get_code_override(AzReturnCode::AzResultCoreErrorHttpAdapter), Option::Some(false));
+        assert_eq!(policy.get_code_override(AzReturnCode::AzResultCoreErrorArg), Option::None);
+    }
+    #[test]
+    fn retry_policy_clamps_to_sdk_range() {
+        assert_eq!(to_msec(u32::MAX), i32::MAX - 1);
+        assert_eq!(to_msec(1_000), 1_000);
+    }
+    #[test]
+    fn retry_policy_status_overrides() {
+        let policy = RetryPolicy::new(NoJitter)
+            .status_override(IotStatus::ServerError, StatusRetry::Never)
+            .status_override(IotStatus::Accepted, StatusRetry::Backoff)
+            .status_override(IotStatus::Accepted, StatusRetry::RetryAfter);
+        assert!(policy.should_retry_status(IotStatus::Throttled));
+        assert!(!policy.should_retry_status(IotStatus::ServerError));
+        assert!(policy.should_retry_status(IotStatus::Accepted));
+        assert!(!policy.should_retry_status(IotStatus::BadRequest));
+        assert_eq!(policy.get_status_override(IotStatus::Accepted), Option::Some(StatusRetry::RetryAfter));
+    }
+    #[test]
+    fn retry_policy_backoff() {
+        let mut policy = RetryPolicy::new(NoJitter).min_delay(1_000).max_delay(10_000).max_attempts(6);
+        let delays: Vec<u32> = policy.backoff().collect();
+        assert_eq!(delays.len(), 6);
+        for pair in delays.windows(2) {
+            assert!(pair[0] <= pair[1]);
+        }
+        assert!(delays[0] >= 1_000);
+        assert_eq!(*delays.last().unwrap(), 10_000);
+    }
+    #[test]
+    fn retry_policy_backoff_reset() {
+        let mut policy = RetryPolicy::new(NoJitter).max_attempts(2);
+        let mut backoff = policy.backoff();
+        let first = backoff.next().unwrap();
+        assert!(backoff.next().is_some());
+        assert_eq!(backoff.next(), Option::None);
+        assert_eq!(backoff.get_attempt(), 2);
+        backoff.reset();
+        assert_eq!(backoff.next(), Option::Some(first));
+    }
+    #[test]
+    fn retry_policy_jitter() {
+        let mut calls = 0;
+        let mut policy = RetryPolicy::new(|| {
+            calls += 1;
+            u32::MAX
+        })
+        .max_jitter(500);
+        let without = RetryPolicy::new(NoJitter).max_jitter(500).delay(1, 0);
+        let with = policy.delay(1, 0);
+        assert!(with >= without && with <= without + 500);
+        drop(policy);
+        assert_eq!(calls, 1);
+    }
+    #[test]
+    fn retry_policy_operation_time() {
+        let mut policy = RetryPolicy::new(NoJitter).min_delay(5_000);
+        assert!(policy.delay(1, 2_000) < policy.delay(1, 0));
+        assert_eq!(policy.delay(1, u32::MAX), 0);
+    }
+    #[test]
+    fn retry_policy_dps_retry_after() {
+        let mut policy = RetryPolicy::new(NoJitter)
+            .max_attempts(3)
+            .status_override(IotStatus::Accepted, StatusRetry::RetryAfter)
+            .status_override(IotStatus::ServiceUnavailable, StatusRetry::Fixed(250));
+        let mut backoff = policy.backoff();
+        assert_eq!(backoff.next_for_status(IotStatus::Accepted, Option::Some(3), 0), Option::Some(3_000));
+        assert_eq!(backoff.next_for_status(IotStatus::ServiceUnavailable, Option::None, 0), Option::Some(250));
+        assert_eq!(backoff.next_for_status(IotStatus::BadRequest, Option::None, 0), Option::None);
+        assert_eq!(backoff.get_attempt(), 2);
+        assert!(backoff.next_for_status(IotStatus::Accepted, Option::None, 0).is_some());
+        assert_eq!(backoff.next_for_status(IotStatus::Accepted, Option::Some(3), 0), Option::None);
+    }
+    #[test]
+    fn retry_policy_default_statuses() {
+        let mut policy = RetryPolicy::new(NoJitter).min_delay(1_000).max_delay(1_000);
+        assert_eq!(policy.get_status_retry(IotStatus::Accepted), StatusRetry::RetryAfter);
+        assert_eq!(policy.get_status_retry(IotStatus::Throttled), StatusRetry::RetryAfter);
+        assert_eq!(policy.get_status_retry(IotStatus::ServiceUnavailable), StatusRetry::RetryAfter);
+        assert_eq!(policy.get_status_retry(IotStatus::BadRequest), StatusRetry::Never);
+        assert_eq!(policy.status_delay(1, 0, IotStatus::Accepted, Option::Some(3)), Option::Some(3_000));
+        assert_eq!(policy.status_delay(1, 0, IotStatus::Throttled, Option::None), Option::Some(1_000));
+        assert_eq!(policy.status_delay(1, 0, IotStatus::Unauthorized, Option::Some(3)), Option::None);
+    }
+}
@@ -0,0 +1,716 @@
+use crate::az_core::{AzSpan, AzSpanMut};
+use crate::az_return_codes::AzReturnCode;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use azsys;
+use core::mem::MaybeUninit;
+use core::str;
+
+// Limits the C SDK enforces with preconditions rather than return codes
+const MAX_NESTING_DEPTH: usize = 64;
+const MAX_UNESCAPED_STRING_SIZE: usize = (i32::MAX / 6) as usize;
+const MAX_FRACTIONAL_DIGITS: u8 = 15;
+const MIN_GROWTH: usize = 64;
+
+#[derive(PartialEq, Clone, Copy)]
+enum JsonContainer {
+    Object,
+    Array,
+}
+
+enum JsonDestination<'a> {
+    Slice(&'a mut [u8]),
+    Vec(Vec<u8>),
+}
+
+/// Builds JSON text with az_json_writer, either into a caller provided buffer or into a
+/// Vec that grows as required. The writer tracks nesting itself so that out of order calls
+/// return AzResultCoreErrorJsonInvalidState instead of tripping a C SDK precondition.
+pub struct JsonWriter<'a> {
+    inner: azsys::az_json_writer,
+    destination: JsonDestination<'a>,
+    containers: [JsonContainer; MAX_NESTING_DEPTH],
+    depth: usize,
+    after_property_name: bool,
+    complete: bool,
+}
+
+impl<'a> JsonWriter<'a> {
+    /// Writes into buffer and fails with AzResultCoreErrorNotEnoughSpace once it is full.
+    pub fn new(buffer: &'a mut [u8]) -> Result<JsonWriter<'a>, AzReturnCode> {
+        let span = AzSpanMut::from(&mut buffer[..]).as_raw();
+        JsonWriter::init(span, JsonDestination::Slice(buffer))
+    }
+
+    /// Writes into a Vec that starts with capacity bytes and grows as required.
+    pub fn with_capacity(capacity: usize) -> Result<JsonWriter<'static>, AzReturnCode> {
+        let mut buffer = vec![0u8; capacity];
+        let span = AzSpanMut::from(buffer.as_mut_slice()).as_raw();
+        JsonWriter::init(span, JsonDestination::Vec(buffer))
+    }
+
+    fn init(span: azsys::az_span, destination: JsonDestination) -> Result<JsonWriter, AzReturnCode> {
+        let mut inner: MaybeUninit<azsys::az_json_writer> = MaybeUninit::uninit();
+        let rc = unsafe { azsys::az_json_writer_init(inner.as_mut_ptr(), span, core::ptr::null()) };
+
+        AzReturnCode::from_i32(rc).into_result()?;
+
+        Ok(JsonWriter {
+            inner: unsafe { inner.assume_init() },
+            destination,
+            containers: [JsonContainer::Object; MAX_NESTING_DEPTH],
+            depth: 0,
+            after_property_name: false,
+            complete: false,
+        })
+    }
+
+    pub fn append_begin_object(&mut self) -> Result<&mut JsonWriter<'a>, AzReturnCode> {
+        self.begin(JsonContainer::Object, |writer| unsafe {
+            azsys::az_json_writer_append_begin_object(writer)
+        })
+    }
+
+    pub fn append_end_object(&mut self) -> Result<&mut JsonWriter<'a>, AzReturnCode> {
+        self.end(JsonContainer::Object, |writer| unsafe {
+            azsys::az_json_writer_append_end_object(writer)
+        })
+    }
+
+    pub fn append_begin_array(&mut self) -> Result<&mut JsonWriter<'a>, AzReturnCode> {
+        self.begin(JsonContainer::Array, |writer| unsafe {
+            azsys::az_json_writer_append_begin_array(writer)
+        })
+    }
+
+    pub fn append_end_array(&mut self) -> Result<&mut JsonWriter<'a>, AzReturnCode> {
+        self.end(JsonContainer::Array, |writer| unsafe {
+            azsys::az_json_writer_append_end_array(writer)
+        })
+    }
+
+    pub fn append_property_name(&mut self, name: &str) -> Result<&mut JsonWriter<'a>, AzReturnCode> {
+        if self.container() != Option::Some(JsonContainer::Object) || self.after_property_name {
+            return Err(AzReturnCode::AzResultCoreErrorJsonInvalidState);
+        }
+
+        if name.len() > MAX_UNESCAPED_STRING_SIZE {
+            return Err(AzReturnCode::AzResultCoreErrorArg);
+        }
+
+        let name = AzSpan::from(name);
+        self.append(|writer| unsafe { azsys::az_json_writer_append_property_name(writer, name.as_raw()) })?;
+        self.after_property_name = true;
+
+        Ok(self)
+    }
+
+    pub fn append_string(&mut self, value: &str) -> Result<&mut JsonWriter<'a>, AzReturnCode> {
+        if value.len() > MAX_UNESCAPED_STRING_SIZE {
+            return Err(AzReturnCode::AzResultCoreErrorArg);
+        }
+
+        let value = AzSpan::from(value);
+        self.value(|writer| unsafe { azsys::az_json_writer_append_string(writer, value.as_raw()) })
+    }
+
+    /// Appends already formatted JSON text as a single value, the C SDK validates it.
+    pub fn append_json_text(&mut self, json_text: &str) -> Result<&mut JsonWriter<'a>, AzReturnCode> {
+        if json_text.is_empty() {
+            return Err(AzReturnCode::AzResultCoreErrorUnexpectedEnd);
+        }
+
+        let json_text = AzSpan::from(json_text);
+        self.value(|writer| unsafe { azsys::az_json_writer_append_json_text(writer, json_text.as_raw()) })
+    }
+
+    pub fn append_bool(&mut self, value: bool) -> Result<&mut JsonWriter<'a>, AzReturnCode> {
+        self.value(|writer| unsafe { azsys::az_json_writer_append_bool(writer, value) })
+    }
+
+    pub fn append_int32(&mut self, value: i32) -> Result<&mut JsonWriter<'a>, AzReturnCode> {
+        self.value(|writer| unsafe { azsys::az_json_writer_append_int32(writer, value) })
+    }
+
+    /// Appends value with at most fractional_digits (0 to 15) digits after the decimal point.
+    pub fn append_double(&mut self, value: f64, fractional_digits: u8) -> Result<&mut JsonWriter<'a>, AzReturnCode> {
+        if fractional_digits > MAX_FRACTIONAL_DIGITS || !value.is_finite() {
+            return Err(AzReturnCode::AzResultCoreErrorArg);
+        }
+
+        self.value(|writer| unsafe {
+            azsys::az_json_writer_append_double(writer, value, fractional_digits as i32)
+        })
+    }
+
+    pub fn append_null(&mut self) -> Result<&mut JsonWriter<'a>, AzReturnCode> {
+        self.value(|writer| unsafe { azsys::az_json_writer_append_null(writer) })
+    }
+
+    /// True once a complete top level value has been written.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner._internal.bytes_written as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match &self.destination {
+            JsonDestination::Slice(buffer) => &buffer[..self.len()],
+            JsonDestination::Vec(buffer) => &buffer[..self.len()],
+        }
+    }
+
+    pub fn as_str(&self) -> Result<&str, AzReturnCode> {
+        str::from_utf8(self.as_bytes()).map_err(|_| AzReturnCode::AzResultCoreErrorUnexpectedChar)
+    }
+
+    pub fn into_vec(self) -> Vec<u8> {
+        let len = self.len();
+
+        match self.destination {
+            JsonDestination::Slice(buffer) => buffer[..len].to_vec(),
+            JsonDestination::Vec(mut buffer) => {
+                buffer.truncate(len);
+                buffer
+            }
+        }
+    }
+
+    // The innermost open object or array
+    fn container(&self) -> Option<JsonContainer> {
+        match self.depth {
+            0 => Option::None,
+            depth => Option::Some(self.containers[depth - 1]),
+        }
+    }
+
+    fn value_allowed(&self) -> bool {
+        match self.container() {
+            Option::None => !self.complete,
+            Option::Some(JsonContainer::Array) => true,
+            Option::Some(JsonContainer::Object) => self.after_property_name,
+        }
+    }
+
+    fn value<F>(&mut self, append: F) -> Result<&mut JsonWriter<'a>, AzReturnCode>
+    where
+        F: Fn(*mut azsys::az_json_writer) -> azsys::az_result,
+    {
+        if !self.value_allowed() {
+            return Err(AzReturnCode::AzResultCoreErrorJsonInvalidState);
+        }
+
+        self.append(append)?;
+        self.after_property_name = false;
+        self.complete = self.depth == 0;
+
+        Ok(self)
+    }
+
+    fn begin<F>(&mut self, container: JsonContainer, append: F) -> Result<&mut JsonWriter<'a>, AzReturnCode>
+    where
+        F: Fn(*mut azsys::az_json_writer) -> azsys::az_result,
+    {
+        if !self.value_allowed() {
+            return Err(AzReturnCode::AzResultCoreErrorJsonInvalidState);
+        }
+
+        if self.depth >= MAX_NESTING_DEPTH {
+            return Err(AzReturnCode::AzResultCoreErrorJsonNestingOverflow);
+        }
+
+        self.append(append)?;
+        self.containers[self.depth] = container;
+        self.depth += 1;
+        self.after_property_name = false;
+
+        Ok(self)
+    }
+
+    fn end<F>(&mut self, container: JsonContainer, append: F) -> Result<&mut JsonWriter<'a>, AzReturnCode>
+    where
+        F: Fn(*mut azsys::az_json_writer) -> azsys::az_result,
+    {
+        if self.container() != Option::Some(container) || self.after_property_name {
+            return Err(AzReturnCode::AzResultCoreErrorJsonInvalidState);
+        }
+
+        self.append(append)?;
+        self.depth -= 1;
+        self.complete = self.depth == 0;
+
+        Ok(self)
+    }
+
+    // The C writer checks for space before changing any state so a failed append can be
+    // retried once the destination has been replaced with a larger copy.
+    fn append<F>(&mut self, append: F) -> Result<(), AzReturnCode>
+    where
+        F: Fn(*mut azsys::az_json_writer) -> azsys::az_result,
+    {
+        loop {
+            let rc = AzReturnCode::from_i32(append(&mut self.inner));
+
+            if rc != AzReturnCode::AzResultCoreErrorNotEnoughSpace || !self.grow() {
+                return rc.into_result();
+            }
+        }
+    }
+
+    fn grow(&mut self) -> bool {
+        match &mut self.destination {
+            JsonDestination::Slice(_) => false,
+            JsonDestination::Vec(buffer) => {
+                let new_len = core::cmp::max(buffer.len() * 2, MIN_GROWTH);
+
+                if new_len > i32::MAX as usize {
+                    return false;
+                }
+
+                buffer.resize(new_len, 0);
+                self.inner._internal.destination_buffer = AzSpanMut::from(buffer.as_mut_slice()).as_raw();
+                true
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum JsonTokenKind {
+    None,
+    BeginObject,
+    EndObject,
+    BeginArray,
+    EndArray,
+    PropertyName,
+    String,
+    Number,
+    True,
+    False,
+    Null,
+}
+
+/// A single token read by JsonReader. The text borrows from the buffer being read and still
+/// contains any escape sequences, use get_string or to_unescaped_string to remove them.
+#[derive(Copy, Clone)]
+pub struct JsonToken<'a> {
+    inner: azsys::az_json_token,
+    slice: AzSpan<'a>,
+}
+
+impl<'a> JsonToken<'a> {
+    pub fn kind(&self) -> JsonTokenKind {
+        match self.inner.kind {
+            azsys::az_json_token_kind_AZ_JSON_TOKEN_BEGIN_OBJECT => JsonTokenKind::BeginObject,
+            azsys::az_json_token_kind_AZ_JSON_TOKEN_END_OBJECT => JsonTokenKind::EndObject,
+            azsys::az_json_token_kind_AZ_JSON_TOKEN_BEGIN_ARRAY => JsonTokenKind::BeginArray,
+            azsys::az_json_token_kind_AZ_JSON_TOKEN_END_ARRAY => JsonTokenKind::EndArray,
+            azsys::az_json_token_kind_AZ_JSON_TOKEN_PROPERTY_NAME => JsonTokenKind::PropertyName,
+            azsys::az_json_token_kind_AZ_JSON_TOKEN_STRING => JsonTokenKind::String,
+            azsys::az_json_token_kind_AZ_JSON_TOKEN_NUMBER => JsonTokenKind::Number,
+            azsys::az_json_token_kind_AZ_JSON_TOKEN_TRUE => JsonTokenKind::True,
+            azsys::az_json_token_kind_AZ_JSON_TOKEN_FALSE => JsonTokenKind::False,
+            azsys::az_json_token_kind_AZ_JSON_TOKEN_NULL => JsonTokenKind::Null,
+            _ => JsonTokenKind::None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.slice.as_bytes()
+    }
+
+    pub fn as_str(&self) -> Result<&'a str, AzReturnCode> {
+        self.slice.as_str().map_err(|_| AzReturnCode::AzResultCoreErrorUnexpectedChar)
+    }
+
+    pub fn has_escaped_chars(&self) -> bool {
+        self.inner._internal.string_has_escaped_chars
+    }
+
+    /// Compares the unescaped text of a string or property name token with expected.
+    pub fn is_text_equal(&self, expected: &str) -> bool {
+        match self.kind() {
+            JsonTokenKind::String | JsonTokenKind::PropertyName => unsafe {
+                azsys::az_json_token_is_text_equal(&self.inner, AzSpan::from(expected).as_raw())
+            },
+            _ => false,
+        }
+    }
+
+    pub fn get_bool(&self) -> Result<bool, AzReturnCode> {
+        match self.kind() {
+            JsonTokenKind::True => Ok(true),
+            JsonTokenKind::False => Ok(false),
+            _ => Err(AzReturnCode::AzResultCoreErrorJsonInvalidState),
+        }
+    }
+
+    pub fn get_u32(&self) -> Result<u32, AzReturnCode> {
+        let mut result: u32 = 0;
+        self.get_number(|token| unsafe { azsys::az_json_token_get_uint32(token, &mut result) })?;
+        Ok(result)
+    }
+
+    pub fn get_i32(&self) -> Result<i32, AzReturnCode> {
+        let mut result: i32 = 0;
+        self.get_number(|token| unsafe { azsys::az_json_token_get_int32(token, &mut result) })?;
+        Ok(result)
+    }
+
+    pub fn get_u64(&self) -> Result<u64, AzReturnCode> {
+        let mut result: u64 = 0;
+        self.get_number(|token| unsafe { azsys::az_json_token_get_uint64(token, &mut result) })?;
+        Ok(result)
+    }
+
+    pub fn get_i64(&self) -> Result<i64, AzReturnCode> {
+        let mut result: i64 = 0;
+        self.get_number(|token| unsafe { azsys::az_json_token_get_int64(token, &mut result) })?;
+        Ok(result)
+    }
+
+    pub fn get_f64(&self) -> Result<f64, AzReturnCode> {
+        let mut result: f64 = 0.0;
+        self.get_number(|token| unsafe { azsys::az_json_token_get_double(token, &mut result) })?;
+        Ok(result)
+    }
+
+    fn get_number<F>(&self, get: F) -> Result<(), AzReturnCode>
+    where
+        F: FnOnce(*const azsys::az_json_token) -> azsys::az_result,
+    {
+        if self.kind() != JsonTokenKind::Number {
+            return Err(AzReturnCode::AzResultCoreErrorJsonInvalidState);
+        }
+
+        AzReturnCode::from_i32(get(&self.inner)).into_result()
+    }
+
+    /// Unescapes a string or property name token into buffer and returns the text written.
+    pub fn get_string<'b>(&self, buffer: &'b mut [u8]) -> Result<&'b str, AzReturnCode> {
+        match self.kind() {
+            JsonTokenKind::String | JsonTokenKind::PropertyName => (),
+            _ => return Err(AzReturnCode::AzResultCoreErrorJsonInvalidState),
+        }
+
+        // The C SDK null terminates the result
+        if buffer.is_empty() {
+            return Err(AzReturnCode::AzResultCoreErrorNotEnoughSpace);
+        }
+
+        let max_size = core::cmp::min(buffer.len(), i32::MAX as usize) as i32;
+        let mut length: i32 = 0;
+        let rc = unsafe {
+            azsys::az_json_token_get_string(&self.inner, buffer.as_mut_ptr() as *mut _, max_size, &mut length)
+        };
+
+        AzReturnCode::from_i32(rc).into_result()?;
+
+        let written = buffer.get(..length as usize).ok_or(AzReturnCode::AzResultCoreErrorNotEnoughSpace)?;
+        str::from_utf8(written).map_err(|_| AzReturnCode::AzResultCoreErrorUnexpectedChar)
+    }
+
+    pub fn to_unescaped_string(&self) -> Result<String, AzReturnCode> {
+        // Unescaping never makes the text longer
+        let mut buffer = vec![0u8; self.slice.len() + 1];
+        self.get_string(&mut buffer).map(String::from)
+    }
+}
+
+/// Reads JSON text one token at a time without allocating, all tokens borrow from the input.
+pub struct JsonReader<'a> {
+    inner: azsys::az_json_reader,
+    buffer: AzSpan<'a>,
+}
+
+impl<'a> JsonReader<'a> {
+    pub fn new(json: &'a [u8]) -> Result<JsonReader<'a>, AzReturnCode> {
+        if json.is_empty() {
+            return Err(AzReturnCode::AzResultCoreErrorUnexpectedEnd);
+        }
+
+        let buffer = AzSpan::from(json);
+        let mut inner: MaybeUninit<azsys::az_json_reader> = MaybeUninit::uninit();
+        let rc = unsafe { azsys::az_json_reader_init(inner.as_mut_ptr(), buffer.as_raw(), core::ptr::null()) };
+
+        AzReturnCode::from_i32(rc).into_result()?;
+
+        Ok(JsonReader {
+            inner: unsafe { inner.assume_init() },
+            buffer,
+        })
+    }
+
+    /// Advances to the next token, AzResultCoreErrorJsonReaderDone marks the end of the input.
+    pub fn next_token(&mut self) -> Result<JsonToken<'a>, AzReturnCode> {
+        let rc = unsafe { azsys::az_json_reader_next_token(&mut self.inner) };
+
+        AzReturnCode::from_i32(rc).into_result()?;
+        self.token()
+    }
+
+    /// Returns the token the reader is positioned on, JsonTokenKind::None before the first read.
+    pub fn token(&self) -> Result<JsonToken<'a>, AzReturnCode> {
+        Ok(JsonToken {
+            inner: self.inner.token,
+            slice: self.buffer.sub_span(self.inner.token.slice)?,
+        })
+    }
+
+    pub fn current_depth(&self) -> usize {
+        self.inner.current_depth as usize
+    }
+
+    /// Moves past the value of the current property or the contents of the current object or
+    /// array, leaving the reader on the last token of it.
+    pub fn skip_children(&mut self) -> Result<JsonToken<'a>, AzReturnCode> {
+        let rc = unsafe { azsys::az_json_reader_skip_children(&mut self.inner) };
+
+        AzReturnCode::from_i32(rc).into_result()?;
+        self.token()
+    }
+
+    /// Walks nested objects from the current object (or the start of the input) following the
+    /// property names in path and returns the value token found at the end.
+    pub fn find_property_path(&mut self, path: &[&str]) -> Result<JsonToken<'a>, AzReturnCode> {
+        let mut token = self.token()?;
+
+        if token.kind() == JsonTokenKind::None {
+            token = self.next_token()?;
+        }
+
+        for name in path {
+            if token.kind() != JsonTokenKind::BeginObject {
+                return Err(AzReturnCode::AzResultCoreErrorItemNotFound);
+            }
+
+            token = self.find_property(name)?;
+        }
+
+        Ok(token)
+    }
+
+    // Scans the members of the object the reader has just entered and returns the value of name
+    fn find_property(&mut self, name: &str) -> Result<JsonToken<'a>, AzReturnCode> {
+        loop {
+            let token = self.next_token()?;
+
+            match token.kind() {
+                JsonTokenKind::PropertyName => {
+                    let value = self.next_token()?;
+
+                    if token.is_text_equal(name) {
+                        return Ok(value);
+                    }
+
+                    self.skip_children()?;
+                }
+                _ => return Err(AzReturnCode::AzResultCoreErrorItemNotFound),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn writer_object() {
+        let mut buffer = [0u8; 100];
+        let mut writer = JsonWriter::new(&mut buffer).unwrap();
+        writer
+            .append_begin_object()
+            .unwrap()
+            .append_property_name("name")
+            .unwrap()
+            .append_string("te\"st")
+            .unwrap()
+            .append_property_name("count")
+            .unwrap()
+            .append_int32(-42)
+            .unwrap()
+            .append_property_name("ratio")
+            .unwrap()
+            .append_double(1.5, 2)
+            .unwrap()
+            .append_property_name("enabled")
+            .unwrap()
+            .append_bool(true)
+            .unwrap()
+            .append_property_name("none")
+            .unwrap()
+            .append_null()
+            .unwrap()
+            .append_end_object()
+            .unwrap();
+        assert!(writer.is_complete());
+        assert_eq!(
+            writer.as_str(),
+            Ok("{\"name\":\"te\\\"st\",\"count\":-42,\"ratio\":1.5,\"enabled\":true,\"none\":null}")
+        );
+    }
+    #[test]
+    fn writer_array() {
+        let mut buffer = [0u8; 100];
+        let mut writer = JsonWriter::new(&mut buffer).unwrap();
+        writer.append_begin_array().unwrap();
+        writer.append_int32(1).unwrap();
+        writer.append_json_text("{\"a\":[2]}").unwrap();
+        writer.append_end_array().unwrap();
+        assert_eq!(writer.as_str(), Ok("[1,{\"a\":[2]}]"));
+    }
+    #[test]
+    fn writer_not_enough_space() {
+        let mut buffer = [0u8; 8];
+        let mut writer = JsonWriter::new(&mut buffer).unwrap();
+        writer.append_begin_object().unwrap();
+        writer.append_property_name("a").unwrap();
+        assert_eq!(
+            writer.append_string("too long for the buffer").err(),
+            Option::Some(AzReturnCode::AzResultCoreErrorNotEnoughSpace)
+        );
+        assert_eq!(writer.as_str(), Ok("{\"a\":"));
+    }
+    #[test]
+    fn writer_growable() {
+        let mut writer = JsonWriter::with_capacity(0).unwrap();
+        writer.append_begin_array().unwrap();
+        for i in 0..100 {
+            writer.append_int32(i).unwrap();
+        }
+        writer.append_end_array().unwrap();
+        let result = String::from_utf8(writer.into_vec()).unwrap();
+        assert!(result.starts_with("[0,1,2,"));
+        assert!(result.ends_with(",98,99]"));
+    }
+    #[test]
+    fn writer_invalid_state() {
+        let mut writer = JsonWriter::with_capacity(32).unwrap();
+        assert_eq!(
+            writer.append_property_name("a").err(),
+            Option::Some(AzReturnCode::AzResultCoreErrorJsonInvalidState)
+        );
+        assert_eq!(writer.append_end_object().err(), Option::Some(AzReturnCode::AzResultCoreErrorJsonInvalidState));
+        writer.append_begin_object().unwrap();
+        assert_eq!(writer.append_int32(1).err(), Option::Some(AzReturnCode::AzResultCoreErrorJsonInvalidState));
+        assert_eq!(writer.append_end_array().err(), Option::Some(AzReturnCode::AzResultCoreErrorJsonInvalidState));
+        writer.append_end_object().unwrap();
+        assert_eq!(writer.append_null().err(), Option::Some(AzReturnCode::AzResultCoreErrorJsonInvalidState));
+        assert_eq!(writer.as_str(), Ok("{}"));
+    }
+    #[test]
+    fn writer_nesting_overflow() {
+        let mut writer = JsonWriter::with_capacity(128).unwrap();
+        for _ in 0..64 {
+            writer.append_begin_array().unwrap();
+        }
+        assert_eq!(
+            writer.append_begin_array().err(),
+            Option::Some(AzReturnCode::AzResultCoreErrorJsonNestingOverflow)
+        );
+    }
+    #[test]
+    fn reader_tokens() {
+        let json = br#"{"a":[1,-2.5,"x\ty"],"b":true,"c":false,"d":null}"#;
+        let mut reader = JsonReader::new(json).unwrap();
+        let mut kinds = Vec::new();
+        loop {
+            match reader.next_token() {
+                Ok(token) => kinds.push(token.kind()),
+                Err(rc) => {
+                    assert_eq!(rc, AzReturnCode::AzResultCoreErrorJsonReaderDone);
+                    break;
+                }
+            }
+        }
+        assert_eq!(
+            kinds,
+            vec![
+                JsonTokenKind::BeginObject,
+                JsonTokenKind::PropertyName,
+                JsonTokenKind::BeginArray,
+                JsonTokenKind::Number,
+                JsonTokenKind::Number,
+                JsonTokenKind::String,
+                JsonTokenKind::EndArray,
+                JsonTokenKind::PropertyName,
+                JsonTokenKind::True,
+                JsonTokenKind::PropertyName,
+                JsonTokenKind::False,
+                JsonTokenKind::PropertyName,
+                JsonTokenKind::Null,
+                JsonTokenKind::EndObject,
+            ]
+        );
+    }
+    #[test]
+    fn reader_values() {
+        let json = br#"[42,-7,1.25,"te\"st",true]"#;
+        let mut reader = JsonReader::new(json).unwrap();
+        assert_eq!(reader.next_token().unwrap().kind(), JsonTokenKind::BeginArray);
+        let token = reader.next_token().unwrap();
+        assert_eq!(token.get_u32(), Ok(42));
+        assert_eq!(token.get_u64(), Ok(42));
+        assert_eq!(token.as_str(), Ok("42"));
+        assert!(reader.next_token().unwrap().get_u32().is_err());
+        assert_eq!(reader.token().unwrap().get_i64(), Ok(-7));
+        assert_eq!(reader.next_token().unwrap().get_f64(), Ok(1.25));
+        let token = reader.next_token().unwrap();
+        assert_eq!(token.as_str(), Ok("te\\\"st"));
+        assert!(token.has_escaped_chars());
+        assert!(token.is_text_equal("te\"st"));
+        let mut buffer = [0u8; 16];
+        assert_eq!(token.get_string(&mut buffer), Ok("te\"st"));
+        assert_eq!(token.to_unescaped_string(), Ok(String::from("te\"st")));
+        assert_eq!(token.get_i32().err(), Option::Some(AzReturnCode::AzResultCoreErrorJsonInvalidState));
+        assert_eq!(reader.next_token().unwrap().get_bool(), Ok(true));
+    }
+    #[test]
+    fn reader_get_string_too_small() {
+        let mut reader = JsonReader::new(b"\"abcdef\"").unwrap();
+        let token = reader.next_token().unwrap();
+        let mut buffer = [0u8; 4];
+        assert_eq!(token.get_string(&mut buffer).err(), Option::Some(AzReturnCode::AzResultCoreErrorNotEnoughSpace));
+    }
+    #[test]
+    fn reader_skip_children() {
+        let mut reader = JsonReader::new(br#"{"a":{"b":[1,2]},"c":3}"#).unwrap();
+        reader.next_token().unwrap();
+        reader.next_token().unwrap();
+        assert_eq!(reader.next_token().unwrap().kind(), JsonTokenKind::BeginObject);
+        assert_eq!(reader.skip_children().unwrap().kind(), JsonTokenKind::EndObject);
+        assert!(reader.next_token().unwrap().is_text_equal("c"));
+        assert_eq!(reader.current_depth(), 1);
+    }
+    #[test]
+    fn reader_find_property_path() {
+        let json = br#"{"skip":{"x":[1,{"y":2}]},"desired":{"telemetry":{"interval":30}},"$version":4}"#;
+        let mut reader = JsonReader::new(json).unwrap();
+        let token = reader.find_property_path(&["desired", "telemetry", "interval"]).unwrap();
+        assert_eq!(token.get_u32(), Ok(30));
+        let mut reader = JsonReader::new(json).unwrap();
+        assert_eq!(reader.find_property_path(&["$version"]).unwrap().get_u32(), Ok(4));
+        let mut reader = JsonReader::new(json).unwrap();
+        assert_eq!(
+            reader.find_property_path(&["desired", "missing"]).err(),
+            Option::Some(AzReturnCode::AzResultCoreErrorItemNotFound)
+        );
+        let mut reader = JsonReader::new(json).unwrap();
+        assert_eq!(
+            reader.find_property_path(&["$version", "x"]).err(),
+            Option::Some(AzReturnCode::AzResultCoreErrorItemNotFound)
+        );
+    }
+    #[test]
+    fn reader_empty() {
+        assert_eq!(JsonReader::new(b"").err(), Option::Some(AzReturnCode::AzResultCoreErrorUnexpectedEnd));
+    }
+}
@@ -2,54 +2,139 @@
 
 use azsys;
 
-use std::fmt;
+use core::fmt;
+use core::str;
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub enum AzReturnCode {
-    AzResultCoreOk = azsys::az_result_core_AZ_OK as isize,
-    AzResultCoreErrorCanceled = azsys::az_result_core_AZ_ERROR_CANCELED as isize,
-    AzResultCoreErrorArg = azsys::az_result_core_AZ_ERROR_ARG as isize,
-    AzResultCoreErrorNotEnoughSpace = azsys::az_result_core_AZ_ERROR_NOT_ENOUGH_SPACE as isize,
-    AzResultCoreErrorNotImplemented = azsys::az_result_core_AZ_ERROR_NOT_IMPLEMENTED as isize,
-    AzResultCoreErrorItemNotFound = azsys::az_result_core_AZ_ERROR_ITEM_NOT_FOUND as isize,
-    AzResultCoreErrorUnexpectedChar = azsys::az_result_core_AZ_ERROR_UNEXPECTED_CHAR as isize,
-    AzResultCoreErrorUnexpectedEnd = azsys::az_result_core_AZ_ERROR_UNEXPECTED_END as isize,
-    AzResultCoreErrorNotSupported = azsys::az_result_core_AZ_ERROR_NOT_SUPPORTED as isize,
-    AzResultCoreErrorDependencyNotProvided =
-        azsys::az_result_core_AZ_ERROR_DEPENDENCY_NOT_PROVIDED as isize,
-    AzResultCoreErrorOutOfMemory = azsys::az_result_core_AZ_ERROR_OUT_OF_MEMORY as isize,
-    AzResultCoreErrorJsonInvalidState = azsys::az_result_core_AZ_ERROR_JSON_INVALID_STATE as isize,
-    AzResultCoreErrorJsonNestingOverflow =
-        azsys::az_result_core_AZ_ERROR_JSON_NESTING_OVERFLOW as isize,
-    AzResultCoreErrorJsonReaderDone = azsys::az_result_core_AZ_ERROR_JSON_READER_DONE as isize,
-    AzResultCoreErrorHttpInvalidState = azsys::az_result_core_AZ_ERROR_HTTP_INVALID_STATE as isize,
-    AzResultCoreErrorHttpPipelineInvalidPolicy =
-        azsys::az_result_core_AZ_ERROR_HTTP_PIPELINE_INVALID_POLICY as isize,
-    AzResultCoreErrorHttpInvalidMethodVerb =
-        azsys::az_result_core_AZ_ERROR_HTTP_INVALID_METHOD_VERB as isize,
-    AzResultCoreErrorHttpAuthenticationFailed =
-        azsys::az_result_core_AZ_ERROR_HTTP_AUTHENTICATION_FAILED as isize,
-    AzResultCoreErrorHttpResponseOverflow =
-        azsys::az_result_core_AZ_ERROR_HTTP_RESPONSE_OVERFLOW as isize,
-    AzResultCoreErrorHttpResponseCouldntResolveHost =
-        azsys::az_result_core_AZ_ERROR_HTTP_RESPONSE_COULDNT_RESOLVE_HOST as isize,
-    AzResultCoreErrorHttpCorruptResponseHeader =
-        azsys::az_result_core_AZ_ERROR_HTTP_CORRUPT_RESPONSE_HEADER as isize,
-    AzResultCoreErrorHttpEndOfHeaders = azsys::az_result_core_AZ_ERROR_HTTP_END_OF_HEADERS as isize,
-    AzResultCoreErrorHttpAdapter = azsys::az_result_core_AZ_ERROR_HTTP_ADAPTER as isize,
-    AzResultIoTErrorTopicNoMatch = azsys::az_result_iot_AZ_ERROR_IOT_TOPIC_NO_MATCH as isize,
-    AzResultIoTErrorEndOfProperties = azsys::az_result_iot_AZ_ERROR_IOT_END_OF_PROPERTIES as isize,
+    AzResultCoreOk,
+    AzResultCoreErrorCanceled,
+    AzResultCoreErrorArg,
+    AzResultCoreErrorNotEnoughSpace,
+    AzResultCoreErrorNotImplemented,
+    AzResultCoreErrorItemNotFound,
+    AzResultCoreErrorUnexpectedChar,
+    AzResultCoreErrorUnexpectedEnd,
+    AzResultCoreErrorNotSupported,
+    AzResultCoreErrorDependencyNotProvided,
+    AzResultCoreErrorOutOfMemory,
+    AzResultCoreErrorJsonInvalidState,
+    AzResultCoreErrorJsonNestingOverflow,
+    AzResultCoreErrorJsonReaderDone,
+    AzResultCoreErrorHttpInvalidState,
+    AzResultCoreErrorHttpPipelineInvalidPolicy,
+    AzResultCoreErrorHttpInvalidMethodVerb,
+    AzResultCoreErrorHttpAuthenticationFailed,
+    AzResultCoreErrorHttpResponseOverflow,
+    AzResultCoreErrorHttpResponseCouldntResolveHost,
+    AzResultCoreErrorHttpCorruptResponseHeader,
+    AzResultCoreErrorHttpEndOfHeaders,
+    AzResultCoreErrorHttpAdapter,
+    AzResultIoTErrorTopicNoMatch,
+    AzResultIoTErrorEndOfProperties,
+    /// A code not known to this crate, such as one added by a newer C SDK
+    Unknown(i32),
 }
 
+static RETURN_CODES: [(AzReturnCode, i32); 25] = [
+    (AzReturnCode::AzResultCoreOk, azsys::az_result_core_AZ_OK),
+    (AzReturnCode::AzResultCoreErrorCanceled, azsys::az_result_core_AZ_ERROR_CANCELED),
+    (AzReturnCode::AzResultCoreErrorArg, azsys::az_result_core_AZ_ERROR_ARG),
+    (AzReturnCode::AzResultCoreErrorNotEnoughSpace, azsys::az_result_core_AZ_ERROR_NOT_ENOUGH_SPACE),
+    (AzReturnCode::AzResultCoreErrorNotImplemented, azsys::az_result_core_AZ_ERROR_NOT_IMPLEMENTED),
+    (AzReturnCode::AzResultCoreErrorItemNotFound, azsys::az_result_core_AZ_ERROR_ITEM_NOT_FOUND),
+    (AzReturnCode::AzResultCoreErrorUnexpectedChar, azsys::az_result_core_AZ_ERROR_UNEXPECTED_CHAR),
+    (AzReturnCode::AzResultCoreErrorUnexpectedEnd, azsys::az_result_core_AZ_ERROR_UNEXPECTED_END),
+    (AzReturnCode::AzResultCoreErrorNotSupported, azsys::az_result_core_AZ_ERROR_NOT_SUPPORTED),
+    (AzReturnCode::AzResultCoreErrorDependencyNotProvided, azsys::az_result_core_AZ_ERROR_DEPENDENCY_NOT_PROVIDED),
+    (AzReturnCode::AzResultCoreErrorOutOfMemory, azsys::az_result_core_AZ_ERROR_OUT_OF_MEMORY),
+    (AzReturnCode::AzResultCoreErrorJsonInvalidState, azsys::az_result_core_AZ_ERROR_JSON_INVALID_STATE),
+    (AzReturnCode::AzResultCoreErrorJsonNestingOverflow, azsys::az_result_core_AZ_ERROR_JSON_NESTING_OVERFLOW),
+    (AzReturnCode::AzResultCoreErrorJsonReaderDone, azsys::az_result_core_AZ_ERROR_JSON_READER_DONE),
+    (AzReturnCode::AzResultCoreErrorHttpInvalidState, azsys::az_result_core_AZ_ERROR_HTTP_INVALID_STATE),
+    (AzReturnCode::AzResultCoreErrorHttpPipelineInvalidPolicy, azsys::az_result_core_AZ_ERROR_HTTP_PIPELINE_INVALID_POLICY),
+    (AzReturnCode::AzResultCoreErrorHttpInvalidMethodVerb, azsys::az_result_core_AZ_ERROR_HTTP_INVALID_METHOD_VERB),
+    (AzReturnCode::AzResultCoreErrorHttpAuthenticationFailed, azsys::az_result_core_AZ_ERROR_HTTP_AUTHENTICATION_FAILED),
+    (AzReturnCode::AzResultCoreErrorHttpResponseOverflow, azsys::az_result_core_AZ_ERROR_HTTP_RESPONSE_OVERFLOW),
+    (AzReturnCode::AzResultCoreErrorHttpResponseCouldntResolveHost, azsys::az_result_core_AZ_ERROR_HTTP_RESPONSE_COULDNT_RESOLVE_HOST),
+    (AzReturnCode::AzResultCoreErrorHttpCorruptResponseHeader, azsys::az_result_core_AZ_ERROR_HTTP_CORRUPT_RESPONSE_HEADER),
+    (AzReturnCode::AzResultCoreErrorHttpEndOfHeaders, azsys::az_result_core_AZ_ERROR_HTTP_END_OF_HEADERS),
+    (AzReturnCode::AzResultCoreErrorHttpAdapter, azsys::az_result_core_AZ_ERROR_HTTP_ADAPTER),
+    (AzReturnCode::AzResultIoTErrorTopicNoMatch, azsys::az_result_iot_AZ_ERROR_IOT_TOPIC_NO_MATCH),
+    (AzReturnCode::AzResultIoTErrorEndOfProperties, azsys::az_result_iot_AZ_ERROR_IOT_END_OF_PROPERTIES),
+];
+
 impl AzReturnCode {
     pub fn from_i32(value: i32) -> AzReturnCode {
-        unsafe { std::mem::transmute(value) }
+        for (code, raw) in RETURN_CODES.iter() {
+            if *raw == value {
+                return *code;
+            }
+        }
+
+        AzReturnCode::Unknown(value)
+    }
+
+    pub fn as_i32(&self) -> i32 {
+        match self {
+            AzReturnCode::Unknown(value) => *value,
+            _ => {
+                for (code, raw) in RETURN_CODES.iter() {
+                    if code == self {
+                        return *raw;
+                    }
+                }
+                unreachable!("Return code missing from table")
+            }
+        }
+    }
+
+    /// Mirrors az_result_succeeded: any code without the error bit set is a success.
+    pub fn is_ok(&self) -> bool {
+        self.as_i32() >= 0
+    }
+
+    pub fn is_err(&self) -> bool {
+        !self.is_ok()
+    }
+
+    /// Converts a successful code into Ok(()) so that callers can use `?`, for example
+    /// `AzReturnCode::from_i32(rc).into_result()?`.
+    pub fn into_result(self) -> Result<(), AzReturnCode> {
+        if self.is_ok() {
+            Ok(())
+        } else {
+            Err(self)
+        }
     }
 }
 
+impl From<i32> for AzReturnCode {
+    fn from(value: i32) -> AzReturnCode {
+        AzReturnCode::from_i32(value)
+    }
+}
+
+impl From<AzReturnCode> for i32 {
+    fn from(value: AzReturnCode) -> i32 {
+        value.as_i32()
+    }
+}
+
+/// Text from the wire that is not valid UTF-8 is reported the way the C SDK reports other
+/// unexpected input.
+impl From<str::Utf8Error> for AzReturnCode {
+    fn from(_: str::Utf8Error) -> AzReturnCode {
+        AzReturnCode::AzResultCoreErrorUnexpectedChar
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AzReturnCode {}
+
 impl fmt::Display for AzReturnCode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
+        match self {
             AzReturnCode::AzResultCoreOk => write!(f, "AzResultCoreOk"),
             AzReturnCode::AzResultCoreErrorCanceled => write!(f, "AzResultCoreErrorCanceled"),
             AzReturnCode::AzResultCoreErrorArg => write!(f, "AzResultCoreErrorArg"),
@@ -113,15 +198,50 @@ impl fmt::Display for AzReturnCode {
             AzReturnCode::AzResultIoTErrorEndOfProperties => {
                 write!(f, "AzResultIoTErrorEndOfProperties")
             }
-            _ => {
-                let work: *const i32 =
-                    unsafe { std::mem::transmute::<&AzReturnCode, *const i32>(self) };
-                write!(
-                    f,
-                    "Unrecognized return code failue {}",
-                    format!("{}", unsafe { *work as i32 })
-                )
-            }
+            AzReturnCode::Unknown(value) => write!(f, "Unrecognized return code failure {}", value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn from_i32_known() {
+        assert_eq!(
+            AzReturnCode::from_i32(azsys::az_result_core_AZ_ERROR_NOT_ENOUGH_SPACE),
+            AzReturnCode::AzResultCoreErrorNotEnoughSpace
+        );
+        assert_eq!(
+            AzReturnCode::AzResultIoTErrorTopicNoMatch.as_i32(),
+            azsys::az_result_iot_AZ_ERROR_IOT_TOPIC_NO_MATCH
+        );
+    }
+    #[test]
+    fn from_i32_unknown() {
+        let rc = AzReturnCode::from(-12345);
+        assert_eq!(rc, AzReturnCode::Unknown(-12345));
+        assert_eq!(i32::from(rc), -12345);
+        assert_eq!(format!("{}", rc), "Unrecognized return code failure -12345");
+    }
+    #[test]
+    fn into_result() {
+        assert!(AzReturnCode::AzResultCoreOk.is_ok());
+        assert_eq!(AzReturnCode::from_i32(azsys::az_result_core_AZ_OK).into_result(), Ok(()));
+        assert!(AzReturnCode::AzResultCoreErrorArg.is_err());
+        assert_eq!(
+            AzReturnCode::AzResultCoreErrorArg.into_result(),
+            Err(AzReturnCode::AzResultCoreErrorArg)
+        );
+    }
+    #[cfg(feature = "std")]
+    #[test]
+    fn question_mark() {
+        fn inner(rc: i32) -> Result<u8, Box<dyn std::error::Error>> {
+            AzReturnCode::from(rc).into_result()?;
+            Ok(1)
         }
+        assert!(inner(azsys::az_result_core_AZ_OK).is_ok());
+        assert!(inner(azsys::az_result_core_AZ_ERROR_ARG).is_err());
     }
 }
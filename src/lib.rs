@@ -1,11 +1,28 @@
+// Builds without std when the default `std` feature is disabled. The crate still needs a global
+// allocator since the clients own copies of the strings the C SDK holds spans over.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
 extern crate azure_embedded_sdk_sys as azsys;
 
+// azure-embedded-sdk-sys v0.1.3 declares size_t as u64, which only matches the C SDK on 64 bit
+// targets. Fail the build elsewhere rather than hand the SDK lengths of the wrong width
+const _: () = assert!(core::mem::size_of::<azsys::size_t>() == core::mem::size_of::<usize>());
+
 pub use az_core::*;
 pub use az_iot::*;
+pub use az_iot_connection_string::*;
+pub use az_iot_provisioning::*;
+pub use az_iot_retry::*;
+pub use az_json::*;
 pub use az_return_codes::*;
 
 pub mod az_core;
 pub mod az_iot;
+pub mod az_iot_connection_string;
+pub mod az_iot_provisioning;
+pub mod az_iot_retry;
+pub mod az_json;
 pub mod az_return_codes;
 
 #[cfg(test)]